@@ -0,0 +1,291 @@
+use crate::circuit::Circuit;
+use crate::field::FiniteField;
+use crate::gate::Gate;
+
+use std::cell::RefCell;
+use std::ops::{Add, Mul, Neg, Not, Sub};
+
+/// Builds a circuit from `Wire` handles combined with arithmetic operators, so that
+/// `(a + 5) * b` adds an `AddConst` and a `Mul` gate.
+pub struct Builder<F: FiniteField> {
+    circuit: RefCell<Circuit<F::Element>>,
+    field: F,
+}
+
+/// The output of a gate in the circuit of a `Builder`.
+pub struct Wire<'a, F: FiniteField> {
+    builder: &'a Builder<F>,
+    id: usize,
+}
+
+impl<F: FiniteField> Builder<F> {
+
+    pub fn new(n_parties: u16, field: F) -> Self {
+        Builder {
+            circuit: RefCell::new(Circuit::new(n_parties)),
+            field
+        }
+    }
+
+    pub fn field(&self) -> &F {
+        &self.field
+    }
+
+    pub fn input(&self, party: usize, circuit_id: usize) -> Wire<'_, F> {
+        self.gate(Gate::new_input(party, circuit_id))
+    }
+
+    /// A public value, known to every party.
+    pub fn constant(&self, value: F::Element) -> Wire<'_, F> {
+        self.gate(Gate::new_const(value))
+    }
+
+    /// Adds all `wires`, or zero if there are none.
+    pub fn sum<'a>(&'a self, wires: impl IntoIterator<Item = Wire<'a, F>>) -> Wire<'a, F> {
+        wires.into_iter().reduce(|acc, wire| acc + wire)
+            .unwrap_or_else(|| self.constant(self.field.zero()))
+    }
+
+    /// Multiplies all `wires` pairwise, so the multiplicative depth is logarithmic in their
+    /// number, or returns one if there are none.
+    pub fn product<'a>(&'a self, wires: impl IntoIterator<Item = Wire<'a, F>>) -> Wire<'a, F> {
+        let mut layer: Vec<Wire<F>> = wires.into_iter().collect();
+        while layer.len() > 1 {
+            layer = layer.chunks(2).map(|pair| match *pair {
+                [first, second] => first * second,
+                [wire] => wire,
+                _ => unreachable!(),
+            }).collect();
+        }
+        layer.pop().unwrap_or_else(|| self.constant(self.field.one()))
+    }
+
+    /// Opens `wire` after the outputs added so far.
+    pub fn output(&self, wire: Wire<F>) {
+        self.circuit.borrow_mut().output(wire);
+    }
+
+    pub fn size(&self) -> usize {
+        self.circuit.borrow().size()
+    }
+
+    pub fn build(self) -> Circuit<F::Element> {
+        self.circuit.into_inner()
+    }
+
+    fn gate(&self, gate: Gate<F::Element>) -> Wire<'_, F> {
+        let id = self.circuit.borrow_mut().add(gate);
+        Wire { builder: self, id }
+    }
+}
+
+impl<'a, F: FiniteField> Wire<'a, F> {
+
+    /// The id of the gate this wire comes from.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn add_const(self, value: F::Element) -> Self {
+        self.builder.gate(Gate::new_add_const(self.id, value))
+    }
+
+    pub fn mul_by_const(self, value: F::Element) -> Self {
+        self.builder.gate(Gate::new_mul_by_const(self.id, value))
+    }
+
+    /// AND of two bits.
+    pub fn and(self, other: Self) -> Self {
+        self * other
+    }
+
+    /// OR of two bits, `a + b - a * b`.
+    pub fn or(self, other: Self) -> Self {
+        self + other - self * other
+    }
+
+    /// `if_one` if this bit is one and `if_zero` if it is zero, `bit * (if_one - if_zero) + if_zero`.
+    pub fn select(self, if_one: Self, if_zero: Self) -> Self {
+        self * (if_one - if_zero) + if_zero
+    }
+
+    fn binary(self, other: Self, gate: fn(usize, usize) -> Gate<F::Element>) -> Self {
+        debug_assert!(std::ptr::eq(self.builder, other.builder), "wires belong to different circuits");
+        self.builder.gate(gate(self.id, other.id))
+    }
+
+    fn element(&self, value: u16) -> F::Element {
+        self.builder.field.element(value)
+    }
+}
+
+// NOTE: derived impls would require `F: Clone + Copy`
+impl<'a, F: FiniteField> Clone for Wire<'a, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, F: FiniteField> Copy for Wire<'a, F> {}
+
+impl<'a, F: FiniteField> Add for Wire<'a, F> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.binary(other, Gate::new_add)
+    }
+}
+
+impl<'a, F: FiniteField> Sub for Wire<'a, F> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.binary(other, Gate::new_sub)
+    }
+}
+
+impl<'a, F: FiniteField> Mul for Wire<'a, F> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.binary(other, Gate::new_mul)
+    }
+}
+
+impl<'a, F: FiniteField> Neg for Wire<'a, F> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self.builder.gate(Gate::new_neg(self.id))
+    }
+}
+
+/// NOT of a bit, `1 - a`.
+impl<'a, F: FiniteField> Not for Wire<'a, F> {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        1 - self
+    }
+}
+
+// Small integer constants, embedded with `FiniteField::element`. Other elements go through
+// `Wire::add_const` and `Wire::mul_by_const`.
+
+impl<'a, F: FiniteField> Add<u16> for Wire<'a, F> {
+    type Output = Self;
+
+    fn add(self, value: u16) -> Self {
+        self.add_const(self.element(value))
+    }
+}
+
+impl<'a, F: FiniteField> Sub<u16> for Wire<'a, F> {
+    type Output = Self;
+
+    fn sub(self, value: u16) -> Self {
+        let field = &self.builder.field;
+        self.add_const(field.sub(field.zero(), field.element(value)))
+    }
+}
+
+impl<'a, F: FiniteField> Mul<u16> for Wire<'a, F> {
+    type Output = Self;
+
+    fn mul(self, value: u16) -> Self {
+        self.mul_by_const(self.element(value))
+    }
+}
+
+impl<'a, F: FiniteField> Add<Wire<'a, F>> for u16 {
+    type Output = Wire<'a, F>;
+
+    fn add(self, wire: Wire<'a, F>) -> Wire<'a, F> {
+        wire + self
+    }
+}
+
+impl<'a, F: FiniteField> Sub<Wire<'a, F>> for u16 {
+    type Output = Wire<'a, F>;
+
+    fn sub(self, wire: Wire<'a, F>) -> Wire<'a, F> {
+        -wire + self
+    }
+}
+
+impl<'a, F: FiniteField> Mul<Wire<'a, F>> for u16 {
+    type Output = Wire<'a, F>;
+
+    fn mul(self, wire: Wire<'a, F>) -> Wire<'a, F> {
+        wire * self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Builder;
+    use crate::field::{Field, BinaryField};
+    use crate::testing::assert_matches_plaintext;
+
+    const ORDER: u8 = 251;
+
+    #[test]
+    fn test_operators() {
+        let builder = Builder::new(3, Field::new(ORDER));
+        let (a, b, c) = (builder.input(0, 0), builder.input(1, 0), builder.input(2, 0));
+        builder.output((a + 5) * (b - c));
+        builder.output(1 - 2 * a * b);
+        builder.output(-c - 3);
+        builder.output(a.mul_by_const(10).add_const(1));
+
+        let output = assert_matches_plaintext(Field::new(ORDER), builder.build(), vec![vec![3], vec![4], vec![1]], 1).unwrap();
+        assert_eq!(output, vec![24, ORDER - 23, ORDER - 4, 31]);
+    }
+
+    #[test]
+    fn test_combinators() {
+        let builder = Builder::new(3, Field::new(ORDER));
+        let bits: Vec<_> = (0..3).map(|party| builder.input(party, 0)).collect();
+        let values: Vec<_> = (0..3).map(|party| builder.input(party, 1)).collect();
+        builder.output(bits[0].and(bits[1]));
+        builder.output(bits[0].or(bits[1]));
+        builder.output(bits[1].or(bits[2]));
+        builder.output(!bits[2]);
+        builder.output(bits[0].select(values[0], values[1]));
+        builder.output(bits[1].select(values[0], values[1]));
+        builder.output(builder.sum(values.clone()));
+        builder.output(builder.product(values));
+        builder.output(builder.sum(vec![]));
+        builder.output(builder.product(vec![]));
+
+        let inputs = vec![vec![1, 7], vec![0, 9], vec![0, 2]];
+        let output = assert_matches_plaintext(Field::new(ORDER), builder.build(), inputs, 1).unwrap();
+        assert_eq!(output, vec![0, 1, 0, 1, 7, 9, 18, 126, 0, 1]);
+    }
+
+    #[test]
+    fn test_product_depth() {
+        let builder = Builder::new(3, Field::new(ORDER));
+        let inputs: Vec<_> = (0..8).map(|circuit_id| builder.input(0, circuit_id)).collect();
+        let product = builder.product(inputs);
+        builder.output(product);
+
+        // 8 inputs and 4 + 2 + 1 multiplications, the last of which is the product
+        assert_eq!(builder.size(), 15);
+        assert_eq!(product.id(), 14);
+    }
+
+    #[test]
+    fn test_binary_field() {
+        // in characteristic 2 the bit operations are XOR and AND
+        let builder = Builder::new(3, BinaryField::gf256());
+        let (a, b) = (builder.input(0, 0), builder.input(1, 0));
+        builder.output(a + b);
+        builder.output(a.or(b));
+        builder.output(!a);
+        builder.output(a.select(builder.input(2, 0), b));
+
+        let output = assert_matches_plaintext(BinaryField::gf256(), builder.build(), vec![vec![1], vec![1], vec![0x53]], 1).unwrap();
+        assert_eq!(output, vec![0, 1, 0, 0x53]);
+    }
+}
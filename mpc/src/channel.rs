@@ -0,0 +1,451 @@
+use crate::{
+    circuit, field, message, party, rng, share_receiver, share_sender, Error
+};
+
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How long `run_parties` lets a party wait for a single message before giving up.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct ChannelSender<Msg>(Sender<Msg>);
+
+pub struct ChannelReceiver<Msg> {
+    rx: Receiver<Msg>,
+    timeout: Option<Duration>,
+}
+
+impl<Msg> ChannelReceiver<Msg> {
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+}
+
+impl<Msg> share_sender::ShareSender<Msg> for ChannelSender<Msg> {
+    fn send(&mut self, msg: Msg) -> Result<(), Error> {
+        self.0.send(msg).map_err(|_| Error::Disconnected)
+    }
+}
+
+impl<Msg> share_receiver::ShareReceiver<Msg> for ChannelReceiver<Msg> {
+    fn recv(&mut self) -> Result<Msg, Error> {
+        match self.timeout {
+            Some(timeout) => self.rx.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => Error::Timeout,
+                RecvTimeoutError::Disconnected => Error::Disconnected,
+            }),
+            None => self.rx.recv().map_err(|_| Error::Disconnected),
+        }
+    }
+}
+
+/// Creates a fully connected network of `n_parties` in-process endpoints.
+///
+/// The i-th entry is party i's receiver together with one sender per party, indexed by
+/// the recipient's id, as expected by `Party::new`.
+pub fn connect<Msg>(n_parties: usize) -> Vec<(ChannelReceiver<Msg>, Vec<ChannelSender<Msg>>)> {
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..n_parties).map(|_| mpsc::channel()).unzip();
+
+    receivers.into_iter()
+        .map(|rx| (
+            ChannelReceiver { rx, timeout: None },
+            senders.iter().map(|tx| ChannelSender(tx.clone())).collect()
+        ))
+        .collect()
+}
+
+/// Runs `circuit` with one `Party` per entry of `secrets`, each on its own thread,
+/// connected through in-process channels. Returns the outputs of every party, in id order.
+pub fn run_parties<F>(field: F,
+                      circuit: circuit::Circuit<F::Element>,
+                      secrets: Vec<Vec<F::Element>>,
+                      threshold: usize) -> Result<Vec<Vec<F::Element>>, Error>
+where F: field::FiniteField + Send + 'static,
+      F::Element: Send + 'static {
+    spawn_parties(field, circuit, secrets, threshold, |_, tx| Box::new(tx), |_, party| party.setup()?.run())
+        .into_iter()
+        .collect()
+}
+
+/// Same as `run_parties`, but party i draws its randomness from `rng::party_rng(seed, i)`,
+/// so that the run, including every message exchanged, is reproducible.
+pub fn run_seeded_parties<F>(field: F,
+                             circuit: circuit::Circuit<F::Element>,
+                             secrets: Vec<Vec<F::Element>>,
+                             threshold: usize,
+                             seed: u64) -> Result<Vec<Vec<F::Element>>, Error>
+where F: field::FiniteField + Send + 'static,
+      F::Element: Send + 'static {
+    spawn_parties(field, circuit, secrets, threshold, |_, tx| Box::new(tx), move |id, party| {
+        party.with_rng(rng::party_rng(seed, id)).setup()?.run()
+    })
+        .into_iter()
+        .collect()
+}
+
+/// Sender of party `id` to one of its peers, as `spawn_parties` hands it to `Party::new`.
+pub(crate) type BoxedSender<DataType> = Box<dyn share_sender::ShareSender<message::Message<DataType>>>;
+
+/// Runs one `Party` per entry of `secrets`, each on its own thread. Party i sends through
+/// `wrap(i, sender)` for each of its senders, and `configure(i, party)` sets it up and runs it.
+pub(crate) fn spawn_parties<F, T, W, C>(field: F,
+                                     circuit: circuit::Circuit<F::Element>,
+                                     secrets: Vec<Vec<F::Element>>,
+                                     threshold: usize,
+                                     wrap: W,
+                                     configure: C) -> Vec<Result<T, Error>>
+where F: field::FiniteField + Send + 'static,
+      F::Element: Send + 'static,
+      T: Send + 'static,
+      W: Fn(usize, ChannelSender<message::Message<F::Element>>) -> BoxedSender<F::Element> + Send + Sync + 'static,
+      C: Fn(usize, party::Party<F>) -> Result<T, Error> + Send + Sync + 'static {
+    let wrap = Arc::new(wrap);
+    let configure = Arc::new(configure);
+
+    let handles: Vec<_> = connect(secrets.len()).into_iter()
+        .zip(secrets)
+        .enumerate()
+        .map(|(id, ((mut rx, txs), secret))| {
+            let field = field.clone();
+            let circuit = circuit.clone();
+            let (wrap, configure) = (wrap.clone(), configure.clone());
+            rx.set_timeout(Some(DEFAULT_TIMEOUT));
+            thread::spawn(move || {
+                let txs = txs.into_iter().map(|tx| wrap(id, tx)).collect();
+                let party = party::Party::new(id, secret, Box::new(rx), txs, field, circuit, threshold)?;
+                configure(id, party)
+            })
+        })
+        .collect();
+
+    handles.into_iter()
+        .map(|handle| handle.join().expect("party thread panicked"))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{connect, run_parties, run_seeded_parties, spawn_parties, ChannelSender};
+    use crate::{
+        circuit::{Circuit, CircuitError}, gate::Gate, field::{Field, Fp, PrimeField},
+        message::Message, multiplication::{Beaver, DegreeReduction, Multiplication}, party::Party,
+        rng::party_rng, share_sender::ShareSender, Error
+    };
+
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    pub(crate) type Transcript = Arc<Mutex<Vec<(usize, usize, Vec<u16>)>>>;
+
+    // Forwards messages while recording (to, round, shares) of each of them.
+    pub(crate) struct Recorder(pub(crate) ChannelSender<Message<u16>>, pub(crate) Transcript);
+
+    impl ShareSender<Message<u16>> for Recorder {
+        fn send(&mut self, msg: Message<u16>) -> Result<(), Error> {
+            self.1.lock().unwrap().push((msg.get_to(), msg.get_round(), msg.get_shares()));
+            self.0.send(msg)
+        }
+    }
+
+    // Adds one to every share sent in `round`.
+    struct Tamper(ChannelSender<Message<u16>>, usize);
+
+    impl ShareSender<Message<u16>> for Tamper {
+        fn send(&mut self, msg: Message<u16>) -> Result<(), Error> {
+            if msg.get_round() != self.1 {
+                return self.0.send(msg);
+            }
+            let shares = msg.get_shares().into_iter().map(|share| (share + 1) % 251).collect();
+            self.0.send(Message::new(msg.get_from(), msg.get_to(), msg.get_round(), shares))
+        }
+    }
+
+    // Outputs of a party along with the parties it reports as misbehaving.
+    type Reported = Result<(Vec<u16>, Vec<usize>), Error>;
+
+    // Sums the inputs of `n_parties` with the last one sending wrong output shares and the
+    // `silent` one, if any, sending nothing after the inputs.
+    fn run_with_cheater(n_parties: usize, silent: Option<usize>) -> Vec<Reported> {
+        let mut circuit = Circuit::new(n_parties as u16);
+        let inputs: Vec<usize> = (0..n_parties).map(|party| circuit.add_input(party, 0)).collect();
+        let sum = inputs[1..].iter().fold(inputs[0], |acc, &input| circuit.add(Gate::new_add(acc, input)));
+        circuit.set_roots(vec![sum]);
+
+        let secrets = (0..n_parties).map(|id| vec![id as u16 + 1]).collect();
+        // round 0 shares the inputs, round 1 opens the sum
+        spawn_parties(Field::new(251u16), circuit, secrets, 1, move |id, tx| {
+            if id == n_parties - 1 {
+                Box::new(Tamper(tx, 1))
+            } else if Some(id) == silent {
+                Box::new(Silent(tx, 1))
+            } else {
+                Box::new(tx)
+            }
+        }, |_, party| party.setup()?.run_with_report())
+    }
+
+    // Drops the messages of `round` and later, as if the sender had left.
+    struct Silent(ChannelSender<Message<u16>>, usize);
+
+    impl ShareSender<Message<u16>> for Silent {
+        fn send(&mut self, msg: Message<u16>) -> Result<(), Error> {
+            if msg.get_round() < self.1 {
+                self.0.send(msg)?;
+            }
+            Ok(())
+        }
+    }
+
+    pub(crate) fn mul_circuit() -> Circuit<u16> {
+        let mut circuit = Circuit::new(3);
+        let a = circuit.add(Gate::new_input(0, 0));
+        let b = circuit.add(Gate::new_input(1, 0));
+        let c = circuit.add(Gate::new_input(2, 0));
+        let ab = circuit.add(Gate::new_mul(a, b));
+        let abc = circuit.add(Gate::new_mul(ab, c));
+        circuit.set_roots(vec![abc]);
+        circuit
+    }
+
+    // Messages sent by each party when running `circuit` seeded with `seed`.
+    fn transcript(circuit: Circuit<u16>, seed: u64) -> Vec<Vec<(usize, usize, Vec<u16>)>> {
+        transcript_with(circuit, seed, DegreeReduction)
+    }
+
+    pub(crate) fn transcript_with<M>(circuit: Circuit<u16>, seed: u64, multiplication: M) -> Vec<Vec<(usize, usize, Vec<u16>)>>
+    where M: Multiplication<Field<u16>> + Clone + Send + Sync + 'static {
+        let logs: Vec<Transcript> = (0..3).map(|_| Transcript::default()).collect();
+        let recorded = logs.clone();
+        let secrets = (0..3).map(|id| vec![id as u16 + 2]).collect();
+        let outputs = spawn_parties(Field::new(251u16), circuit, secrets, 1, move |id, tx| {
+            Box::new(Recorder(tx, recorded[id].clone()))
+        }, move |id, party| {
+            party.with_rng(party_rng(seed, id)).with_multiplication(multiplication.clone()).setup()?.run()
+        });
+
+        for output in outputs {
+            output.unwrap();
+        }
+        logs.iter().map(|log| log.lock().unwrap().clone()).collect()
+    }
+
+    #[test]
+    fn test_run_parties() {
+        let mut circuit = Circuit::new(3);
+        let a = circuit.add(Gate::new_input(0, 0));
+        let b = circuit.add(Gate::new_input(1, 0));
+        let c = circuit.add(Gate::new_input(2, 0));
+        let ab = circuit.add(Gate::new_mul(a, b));
+        let abc = circuit.add(Gate::new_mul(ab, c));
+        let sum = circuit.add(Gate::new_add(a, b));
+        let scaled = circuit.add(Gate::new_mul_by_const(sum, 3));
+        circuit.set_roots(vec![abc, scaled]);
+
+        let outputs = run_parties(Field::new(251u16), circuit, vec![vec![2], vec![5], vec![7]], 1).unwrap();
+
+        assert_eq!(outputs, vec![vec![70, 21]; 3]);
+    }
+
+    #[test]
+    fn test_run_parties_fp() {
+        type F = Fp<{ crate::field::primes::MERSENNE_61 }>;
+
+        let mut circuit = Circuit::new(3);
+        let a = circuit.add(Gate::new_input(0, 0));
+        let b = circuit.add(Gate::new_input(1, 0));
+        let c = circuit.add(Gate::new_input(2, 0));
+        let ab = circuit.add(Gate::new_mul(a, b));
+        let abc = circuit.add(Gate::new_mul_by_const(ab, F::new(2)));
+        let sum = circuit.add(Gate::new_add(abc, c));
+        circuit.set_roots(vec![sum]);
+
+        let secrets = vec![vec![F::new(1 << 40)], vec![F::new(1 << 30)], vec![F::new(5)]];
+        let outputs = run_parties(PrimeField, circuit, secrets, 1).unwrap();
+
+        assert_eq!(outputs, vec![vec![F::new(1 << 10) + F::new(5)]; 3]);
+    }
+
+    #[test]
+    fn test_invalid_circuit() {
+        let mut circuit = Circuit::new(3);
+        let a = circuit.add(Gate::new_input(0, 0));
+        let ab = circuit.add(Gate::new_mul(a, 2));
+        circuit.add(Gate::new_input(1, 0));
+        circuit.set_roots(vec![ab]);
+
+        // every party rejects the circuit before sending anything, so none waits for a share
+        let result = run_parties(Field::new(251u16), circuit, vec![vec![2], vec![5], vec![7]], 1);
+
+        assert_eq!(result, Err(Error::InvalidCircuit(CircuitError::ForwardReference { gate: 1, operand: 2 })));
+    }
+
+    #[test]
+    fn test_seeded_transcript() {
+        assert_eq!(transcript(mul_circuit(), 5), transcript(mul_circuit(), 5));
+        assert_ne!(transcript(mul_circuit(), 5), transcript(mul_circuit(), 6));
+    }
+
+    #[test]
+    fn test_mul_rounds() {
+        let mut circuit = Circuit::new(3);
+        let inputs: Vec<usize> = (0..3).map(|party| circuit.add_input(party, 0)).collect();
+        let products: Vec<usize> = [(0, 1), (1, 2), (0, 2), (0, 0)].iter()
+            .map(|&(i, j)| circuit.add_mul(inputs[i], inputs[j]))
+            .collect();
+        let product = circuit.add_mul(products[0], products[1]);
+        circuit.set_roots(vec![product, products[3]]);
+
+        // one round each for the setup of the five products, the inputs, the four products of
+        // depth 1, the one of depth 2 and the outputs
+        for messages in transcript(circuit, 1) {
+            let rounds: Vec<(usize, usize)> = messages.iter().map(|(_, round, shares)| (*round, shares.len())).collect();
+            assert_eq!(rounds, vec![(0, 10), (0, 10), (1, 1), (1, 1), (2, 4), (2, 4), (3, 1), (3, 1), (4, 2), (4, 2)]);
+        }
+    }
+
+    #[test]
+    fn test_run_seeded_parties() {
+        let outputs = run_seeded_parties(Field::new(251u16), mul_circuit(), vec![vec![3], vec![4], vec![5]], 1, 42).unwrap();
+
+        assert_eq!(outputs, vec![vec![60]; 3]);
+    }
+
+    #[test]
+    fn test_wrong_output_share() {
+        // the first degree + t + 1 = 3 shares to arrive suffice if they agree, and a fourth
+        // corrects the wrong one if it is among them
+        for output in run_with_cheater(5, None) {
+            assert!(matches!(output, Ok((sum, _)) if sum == vec![15]));
+        }
+
+        // with party 2 gone the cheater is still outvoted, and reported if it had to be
+        let outputs = run_with_cheater(5, Some(2));
+        for id in [0, 1, 3] {
+            assert!(matches!(&outputs[id], Ok((sum, bad)) if *sum == vec![15] && bad.iter().all(|&party| party == 4)));
+        }
+        assert_eq!(outputs[4], Ok((vec![15], vec![])));
+
+        // the first 3 of 4 only detect it, if it arrives among them, and then the last share
+        // is awaited to correct it
+        for output in run_with_cheater(4, None) {
+            assert!(matches!(output, Ok((sum, _)) if sum == vec![10]));
+        }
+    }
+
+    #[test]
+    fn test_party_leaves() {
+        // rounds 0 and 1 preprocess the triples and round 2 shares the inputs; the Beaver
+        // multiplications and the outputs then only need degree + t + 1 = 3 agreeing shares
+        let mut circuit = Circuit::new(4);
+        let inputs: Vec<usize> = (0..4).map(|party| circuit.add_input(party, 0)).collect();
+        let product = inputs[1..].iter().fold(inputs[0], |acc, &input| circuit.add_mul(acc, input));
+        circuit.set_roots(vec![product]);
+
+        let secrets = (0..4).map(|id| vec![id as u16 + 3]).collect();
+        let outputs = spawn_parties(Field::new(251u16), circuit, secrets, 1, |id, tx| {
+            if id == 3 { Box::new(Silent(tx, 3)) } else { Box::new(tx) }
+        }, |_, party| party.with_multiplication(Beaver).setup()?.run());
+
+        // 3 * 4 * 5 * 6 = 360
+        assert_eq!(outputs, vec![Ok(vec![109]); 4]);
+    }
+
+    // Output shares of the parties watched by the forgers, by id.
+    type Board = Arc<Mutex<Vec<Option<u16>>>>;
+
+    // Posts the share sent in `round` to the board.
+    struct Leak(ChannelSender<Message<u16>>, usize, Board);
+
+    impl ShareSender<Message<u16>> for Leak {
+        fn send(&mut self, msg: Message<u16>) -> Result<(), Error> {
+            if msg.get_round() == self.1 {
+                self.2.lock().unwrap()[msg.get_from()] = Some(msg.get_shares()[0]);
+            }
+            self.0.send(msg)
+        }
+    }
+
+    // Sends in `round`, instead of its share, the value at its point of the polynomial of
+    // degree 2 through the shares of parties 1 and 2 on the board and 99 at zero.
+    struct Forge(ChannelSender<Message<u16>>, usize, Board);
+
+    impl ShareSender<Message<u16>> for Forge {
+        fn send(&mut self, msg: Message<u16>) -> Result<(), Error> {
+            if msg.get_round() != self.1 {
+                return self.0.send(msg);
+            }
+            let honest = loop {
+                if let [_, Some(first), Some(second), ..] = self.2.lock().unwrap()[..] {
+                    break [first, second];
+                }
+                thread::sleep(Duration::from_millis(1));
+            };
+
+            let field = Field::new(251u16);
+            let points = [(0, 99), (2, honest[0]), (3, honest[1])];
+            let x = msg.get_from() as u16 + 1;
+            let share = points.iter()
+                .map(|&(xj, yj)| points.iter()
+                    .filter(|&&(xk, _)| xk != xj)
+                    .fold(yj, |acc, &(xk, _)| field.mul(acc, field.mul(field.sub(x, xk), field.inv(field.sub(xj, xk))))))
+                .fold(0, |acc, term| field.add(acc, term));
+            self.0.send(Message::new(msg.get_from(), msg.get_to(), msg.get_round(), vec![share]))
+        }
+    }
+
+    // Holds back the messages of `round`, so that the others' arrive first.
+    struct Delay(ChannelSender<Message<u16>>, usize);
+
+    impl ShareSender<Message<u16>> for Delay {
+        fn send(&mut self, msg: Message<u16>) -> Result<(), Error> {
+            if msg.get_round() == self.1 {
+                thread::sleep(Duration::from_millis(100));
+            }
+            self.0.send(msg)
+        }
+    }
+
+    #[test]
+    fn test_forged_output_shares() {
+        // with n = 7 and t = 2, parties 3 and 4 send output shares which, along with those of
+        // parties 1 and 2, lie on a polynomial of degree 2 opening 99; arriving before those
+        // of parties 5 and 6, they outvote the share of party 0 but only 4 agree, short of
+        // degree + t + 1 = 5, so the others are awaited and the forgery is corrected
+        let mut circuit = Circuit::new(7);
+        let inputs: Vec<usize> = (0..7).map(|party| circuit.add_input(party, 0)).collect();
+        let sum = inputs[1..].iter().fold(inputs[0], |acc, &input| circuit.add(Gate::new_add(acc, input)));
+        circuit.set_roots(vec![sum]);
+
+        let board: Board = Arc::new(Mutex::new(vec![None; 7]));
+        let secrets = (0..7).map(|_| vec![6]).collect();
+        // round 0 shares the inputs, round 1 opens the sum
+        let outputs = spawn_parties(Field::new(251u16), circuit, secrets, 2, move |id, tx| {
+            match id {
+                1 | 2 => Box::new(Leak(tx, 1, board.clone())),
+                3 | 4 => Box::new(Forge(tx, 1, board.clone())),
+                5 | 6 => Box::new(Delay(tx, 1)),
+                _ => Box::new(tx),
+            }
+        }, |_, party| party.setup()?.run_with_report());
+
+        for id in [0, 1, 2, 5, 6] {
+            assert_eq!(outputs[id], Ok((vec![42], vec![3, 4])));
+        }
+    }
+
+    #[test]
+    fn test_peer_gone() {
+        // the setup needs every party, so one which cannot be reached fails it
+        let (rx, txs) = connect(3).swap_remove(0);
+        let txs = txs.into_iter().map(|tx| Box::new(tx) as _).collect();
+        let result = Party::new(0, vec![2], Box::new(rx), txs, Field::new(251u16), mul_circuit(), 1)
+            .and_then(Party::setup)
+            .and_then(Party::run);
+
+        assert_eq!(result, Err(Error::Disconnected));
+    }
+}
@@ -1,7 +1,37 @@
+pub mod optimize;
+
+use crate::builder::Wire;
+use crate::field::FiniteField;
 use crate::gate;
 
+use std::fmt;
 use std::iter::Iterator;
 
+/// Why a circuit cannot be evaluated, see `Circuit::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CircuitError {
+    UnknownGate { gate: usize, operand: usize },
+    ForwardReference { gate: usize, operand: usize },
+    UnknownParty { gate: usize, party: usize, n_parties: u16 },
+    UnknownRoot(usize),
+}
+
+impl fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CircuitError::UnknownGate { gate, operand } =>
+                write!(f, "gate {} reads gate {}, which does not exist", gate, operand),
+            CircuitError::ForwardReference { gate, operand } =>
+                write!(f, "gate {} reads gate {}, which is not evaluated before it", gate, operand),
+            CircuitError::UnknownParty { gate, party, n_parties } =>
+                write!(f, "input gate {} belongs to party {}, but there are only {} parties", gate, party, n_parties),
+            CircuitError::UnknownRoot(root) => write!(f, "root {} is not a gate", root),
+        }
+    }
+}
+
+impl std::error::Error for CircuitError {}
+
 #[derive(Clone)]
 pub struct Circuit<DataType: Clone> {
     gates: Vec<gate::Gate<DataType>>,
@@ -23,11 +53,89 @@ impl<DataType: Clone> Circuit<DataType> {
         self.roots = gate_ids;
     }
 
+    /// Opens `wire` after the outputs added so far.
+    pub fn output<F: FiniteField<Element = DataType>>(&mut self, wire: Wire<F>) {
+        self.roots.push(wire.id());
+    }
+
     pub fn add(&mut self, gate: gate::Gate<DataType>) -> usize {
         self.gates.push(gate);
         self.gates.len() - 1
     }
 
+    pub fn add_input(&mut self, party: usize, circuit_id: usize) -> usize {
+        self.add(gate::Gate::new_input(party, circuit_id))
+    }
+
+    /// Adds a public value, known to every party.
+    pub fn add_const(&mut self, value: DataType) -> usize {
+        self.add(gate::Gate::new_const(value))
+    }
+
+    pub fn add_add(&mut self, first: usize, second: usize) -> usize {
+        self.add(gate::Gate::new_add(first, second))
+    }
+
+    pub fn add_add_const(&mut self, first: usize, second: DataType) -> usize {
+        self.add(gate::Gate::new_add_const(first, second))
+    }
+
+    pub fn add_sub(&mut self, first: usize, second: usize) -> usize {
+        self.add(gate::Gate::new_sub(first, second))
+    }
+
+    pub fn add_neg(&mut self, first: usize) -> usize {
+        self.add(gate::Gate::new_neg(first))
+    }
+
+    pub fn add_mul_by_const(&mut self, first: usize, second: DataType) -> usize {
+        self.add(gate::Gate::new_mul_by_const(first, second))
+    }
+
+    pub fn add_mul(&mut self, first: usize, second: usize) -> usize {
+        self.add(gate::Gate::new_mul(first, second))
+    }
+
+    /// Checks that every gate only reads gates added before it, that input gates belong to
+    /// one of the `n_parties` parties and that every root is a gate. `Party::run` evaluates
+    /// gates in order and would otherwise fail mid-protocol.
+    pub fn validate(&self) -> Result<(), CircuitError> {
+        for (gate_id, gate) in self.gates.iter().enumerate() {
+            for operand in gate.get_operands() {
+                if operand >= self.gates.len() {
+                    return Err(CircuitError::UnknownGate { gate: gate_id, operand });
+                }
+                if operand >= gate_id {
+                    return Err(CircuitError::ForwardReference { gate: gate_id, operand });
+                }
+            }
+            if let gate::Gate::Input { party, circuit_id: _, output: _ } = gate {
+                if *party >= self.n_parties as usize {
+                    return Err(CircuitError::UnknownParty { gate: gate_id, party: *party, n_parties: self.n_parties });
+                }
+            }
+        }
+
+        match self.roots.iter().find(|&&root| root >= self.gates.len()) {
+            Some(&root) => Err(CircuitError::UnknownRoot(root)),
+            None => Ok(()),
+        }
+    }
+
+    /// Multiplicative depth of every gate: the largest number of `Mul` gates on a path from
+    /// an input or a constant to it. `Mul` gates of the same depth never read one another.
+    pub fn depths(&self) -> Vec<usize> {
+        let mut depths: Vec<usize> = Vec::with_capacity(self.gates.len());
+        for gate in &self.gates {
+            let depth = gate.get_operands().into_iter().map(|operand| depths[operand]).max().unwrap_or(0);
+            depths.push(match gate {
+                gate::Gate::Mul { first: _, second: _, output: _ } => depth + 1,
+                _ => depth,
+            });
+        }
+        depths
+    }
+
     pub(crate) fn get_n_parties(&self) -> u16 {
         self.n_parties
     }
@@ -45,10 +153,58 @@ impl<DataType: Clone> Circuit<DataType> {
     }
 
     pub fn traverse(&self) -> impl Iterator<Item = usize> {
-        (0..self.gates.len()).into_iter()
+        0..self.gates.len()
     }
 
     pub fn size(&self) -> usize {
         self.gates.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Circuit, CircuitError};
+    use crate::gate::Gate;
+
+    fn circuit(gates: Vec<Gate<u8>>, roots: Vec<usize>) -> Circuit<u8> {
+        let mut circuit = Circuit::new(2);
+        for gate in gates {
+            circuit.add(gate);
+        }
+        circuit.set_roots(roots);
+        circuit
+    }
+
+    #[test]
+    fn test_validate() {
+        let inputs = || vec![Gate::new_input(0, 0), Gate::new_input(1, 0)];
+        let valid = circuit(inputs().into_iter().chain(vec![Gate::new_mul(0, 1), Gate::new_neg(2)]).collect(), vec![3, 0]);
+        assert_eq!(valid.validate(), Ok(()));
+
+        let cases = vec![
+            (Gate::new_add(1, 5), vec![2], CircuitError::UnknownGate { gate: 2, operand: 5 }),
+            (Gate::new_mul(2, 0), vec![2], CircuitError::ForwardReference { gate: 2, operand: 2 }),
+            (Gate::new_input(2, 0), vec![2], CircuitError::UnknownParty { gate: 2, party: 2, n_parties: 2 }),
+            (Gate::new_sub(0, 1), vec![2, 3], CircuitError::UnknownRoot(3)),
+        ];
+        for (gate, roots, expected) in cases {
+            let invalid = circuit(inputs().into_iter().chain(vec![gate]).collect(), roots);
+            assert_eq!(invalid.validate(), Err(expected));
+        }
+    }
+
+    #[test]
+    fn test_depths() {
+        let mut circuit = Circuit::new(2);
+        let a = circuit.add_input(0, 0);
+        let b = circuit.add_input(1, 0);
+        let ab = circuit.add_mul(a, b);
+        let sum = circuit.add_add(ab, a);
+        let square = circuit.add_mul(a, a);
+        let product = circuit.add_mul(sum, square);
+        circuit.add_const(1);
+        circuit.add_neg(product);
+
+        assert_eq!(circuit.depths(), vec![0, 0, 1, 1, 1, 2, 0, 2]);
+    }
+}
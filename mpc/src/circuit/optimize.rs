@@ -0,0 +1,372 @@
+//! Passes rewriting a circuit into an equivalent one with fewer gates. Every `Mul` gate costs
+//! `Party::run` a round of communication, every other gate is local but still evaluated.
+
+use crate::circuit::{Circuit, CircuitError};
+use crate::field::FiniteField;
+use crate::gate::Gate;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Gate counts before and after `optimize`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    gates_before: usize,
+    gates_after: usize,
+    muls_before: usize,
+    muls_after: usize,
+}
+
+impl Report {
+
+    pub fn get_gates_before(&self) -> usize {
+        self.gates_before
+    }
+
+    pub fn get_gates_after(&self) -> usize {
+        self.gates_after
+    }
+
+    pub fn get_muls_before(&self) -> usize {
+        self.muls_before
+    }
+
+    pub fn get_muls_after(&self) -> usize {
+        self.muls_after
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {} gates, {} -> {} multiplications",
+               self.gates_before, self.gates_after, self.muls_before, self.muls_after)
+    }
+}
+
+/// Runs `fold_constants`, `eliminate_common_subexpressions` and `eliminate_dead_gates`.
+///
+/// Fails if `circuit` does not pass `Circuit::validate`.
+pub fn optimize<F: FiniteField>(circuit: &Circuit<F::Element>, field: &F) -> Result<(Circuit<F::Element>, Report), CircuitError> {
+    circuit.validate()?;
+
+    let optimized = eliminate_dead_gates(&eliminate_common_subexpressions(&fold_constants(circuit, field)));
+    let report = Report {
+        gates_before: circuit.size(),
+        gates_after: optimized.size(),
+        muls_before: count_muls(circuit),
+        muls_after: count_muls(&optimized),
+    };
+    Ok((optimized, report))
+}
+
+/// Evaluates gates whose operands are all `Const` gates, turns `Add` and `Mul` with a constant
+/// operand into `AddConst` and `MulByConst`, and merges chains of `MulByConst` into one gate.
+pub fn fold_constants<F: FiniteField>(circuit: &Circuit<F::Element>, field: &F) -> Circuit<F::Element> {
+    rewrite(circuit, |optimized, _, gate| {
+        let constant = |id: usize| match optimized.get_gate(id) {
+            Gate::Const { value, output: _ } => Some(value.clone()),
+            _ => None,
+        };
+
+        let folded = match gate {
+            Gate::Add { first, second, output: _ } => match (constant(first), constant(second)) {
+                (Some(a), Some(b)) => Gate::new_const(field.add(a, b)),
+                (Some(a), None) => Gate::new_add_const(second, a),
+                (None, Some(b)) => Gate::new_add_const(first, b),
+                (None, None) => gate,
+            },
+            Gate::Sub { first, second, output: _ } => match (constant(first), constant(second)) {
+                (Some(a), Some(b)) => Gate::new_const(field.sub(a, b)),
+                (None, Some(b)) => Gate::new_add_const(first, field.sub(field.zero(), b)),
+                _ => gate,
+            },
+            Gate::Mul { first, second, output: _ } => match (constant(first), constant(second)) {
+                (Some(a), Some(b)) => Gate::new_const(field.mul(a, b)),
+                (Some(a), None) => Gate::new_mul_by_const(second, a),
+                (None, Some(b)) => Gate::new_mul_by_const(first, b),
+                (None, None) => gate,
+            },
+            Gate::Neg { first, output: _ } => match constant(first) {
+                Some(a) => Gate::new_const(field.sub(field.zero(), a)),
+                None => gate,
+            },
+            Gate::AddConst { first, ref second, output: _ } => match constant(first) {
+                Some(a) => Gate::new_const(field.add(a, second.clone())),
+                None => gate,
+            },
+            Gate::MulByConst { first, ref second, output: _ } => match (constant(first), optimized.get_gate(first)) {
+                (Some(a), _) => Gate::new_const(field.mul(a, second.clone())),
+                (None, Gate::MulByConst { first: inner, second: scale, output: _ }) =>
+                    Gate::new_mul_by_const(*inner, field.mul(scale.clone(), second.clone())),
+                _ => gate,
+            },
+            _ => gate,
+        };
+
+        match folded {
+            Gate::MulByConst { first, ref second, output: _ } if *second == field.one() => Rewrite::Reuse(first),
+            Gate::AddConst { first, ref second, output: _ } if *second == field.zero() => Rewrite::Reuse(first),
+            folded => Rewrite::Add(folded),
+        }
+    })
+}
+
+/// Merges gates computing the same operation on the same operands, including duplicated
+/// input gates and `Add` or `Mul` gates with swapped operands.
+pub fn eliminate_common_subexpressions<T: Clone + PartialEq>(circuit: &Circuit<T>) -> Circuit<T> {
+    // gates are compared by operation and operands first, then by their constant
+    let mut seen: HashMap<Key, Vec<(Option<T>, usize)>> = HashMap::new();
+
+    rewrite(circuit, |optimized, _, gate| {
+        let gate = match gate {
+            Gate::Add { first, second, output: _ } if first > second => Gate::new_add(second, first),
+            Gate::Mul { first, second, output: _ } if first > second => Gate::new_mul(second, first),
+            gate => gate,
+        };
+        let (key, constant) = key(&gate);
+
+        let candidates = seen.entry(key).or_default();
+        match candidates.iter().find(|(other, _)| *other == constant) {
+            Some(&(_, id)) => Rewrite::Reuse(id),
+            None => {
+                candidates.push((constant, optimized.size()));
+                Rewrite::Add(gate)
+            }
+        }
+    })
+}
+
+/// Removes gates which no root depends on.
+pub fn eliminate_dead_gates<T: Clone>(circuit: &Circuit<T>) -> Circuit<T> {
+    let mut live = vec![false; circuit.size()];
+    for root in circuit.get_roots() {
+        live[root] = true;
+    }
+    // operands come before the gates reading them
+    for gate_id in (0..circuit.size()).rev() {
+        if live[gate_id] {
+            for operand in circuit.get_gate(gate_id).get_operands() {
+                live[operand] = true;
+            }
+        }
+    }
+
+    rewrite(circuit, |_, gate_id, gate| {
+        if live[gate_id] {
+            Rewrite::Add(gate)
+        } else {
+            Rewrite::Drop
+        }
+    })
+}
+
+fn count_muls<T: Clone>(circuit: &Circuit<T>) -> usize {
+    circuit.traverse()
+        .filter(|&gate_id| matches!(circuit.get_gate(gate_id), Gate::Mul { first: _, second: _, output: _ }))
+        .count()
+}
+
+enum Rewrite<T: Clone> {
+    Add(Gate<T>),
+    Reuse(usize),
+    Drop,
+}
+
+/// Builds a new circuit from the gates of `circuit` in order. `pass` sees each gate id and gate with its
+/// operands renumbered into the new circuit, and either adds a gate, reuses an existing one
+/// or drops it if nothing reads it. Gates reading a dropped gate are dropped too.
+fn rewrite<T: Clone>(circuit: &Circuit<T>, mut pass: impl FnMut(&Circuit<T>, usize, Gate<T>) -> Rewrite<T>) -> Circuit<T> {
+    let mut optimized = Circuit::new(circuit.get_n_parties());
+    let mut ids: Vec<Option<usize>> = Vec::with_capacity(circuit.size());
+
+    for gate_id in circuit.traverse() {
+        if circuit.get_gate(gate_id).get_operands().into_iter().any(|operand| ids[operand].is_none()) {
+            ids.push(None);
+            continue;
+        }
+
+        let renumber = |id: usize| ids[id].unwrap();
+        let gate = match circuit.get_gate(gate_id) {
+            Gate::Input { party, circuit_id, output: _ } => Gate::new_input(*party, *circuit_id),
+            Gate::Const { value, output: _ } => Gate::new_const(value.clone()),
+            Gate::Add { first, second, output: _ } => Gate::new_add(renumber(*first), renumber(*second)),
+            Gate::MulByConst { first, second, output: _ } => Gate::new_mul_by_const(renumber(*first), second.clone()),
+            Gate::AddConst { first, second, output: _ } => Gate::new_add_const(renumber(*first), second.clone()),
+            Gate::Sub { first, second, output: _ } => Gate::new_sub(renumber(*first), renumber(*second)),
+            Gate::Neg { first, output: _ } => Gate::new_neg(renumber(*first)),
+            Gate::Mul { first, second, output: _ } => Gate::new_mul(renumber(*first), renumber(*second)),
+        };
+
+        ids.push(match pass(&optimized, gate_id, gate) {
+            Rewrite::Add(gate) => Some(optimized.add(gate)),
+            Rewrite::Reuse(id) => Some(id),
+            Rewrite::Drop => None,
+        });
+    }
+
+    optimized.set_roots(circuit.get_roots().into_iter().map(|root| ids[root].expect("a root was dropped")).collect());
+    optimized
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Input(usize, usize),
+    Const,
+    Add(usize, usize),
+    MulByConst(usize),
+    AddConst(usize),
+    Sub(usize, usize),
+    Neg(usize),
+    Mul(usize, usize),
+}
+
+fn key<T: Clone>(gate: &Gate<T>) -> (Key, Option<T>) {
+    match gate {
+        Gate::Input { party, circuit_id, output: _ } => (Key::Input(*party, *circuit_id), None),
+        Gate::Const { value, output: _ } => (Key::Const, Some(value.clone())),
+        Gate::Add { first, second, output: _ } => (Key::Add(*first, *second), None),
+        Gate::MulByConst { first, second, output: _ } => (Key::MulByConst(*first), Some(second.clone())),
+        Gate::AddConst { first, second, output: _ } => (Key::AddConst(*first), Some(second.clone())),
+        Gate::Sub { first, second, output: _ } => (Key::Sub(*first, *second), None),
+        Gate::Neg { first, output: _ } => (Key::Neg(*first), None),
+        Gate::Mul { first, second, output: _ } => (Key::Mul(*first, *second), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{optimize, eliminate_common_subexpressions, eliminate_dead_gates, fold_constants};
+    use crate::{circuit::{Circuit, CircuitError}, field::Field, gate::Gate, testing::evaluate};
+
+    use proptest::prelude::*;
+
+    const ORDER: u16 = 251;
+
+    fn inputs(circuit: &mut Circuit<u16>) -> Vec<usize> {
+        (0..3).map(|party| circuit.add_input(party, 0)).collect()
+    }
+
+    #[test]
+    fn test_eliminate_common_subexpressions() {
+        let mut circuit = Circuit::new(3);
+        let x = inputs(&mut circuit);
+        let duplicate = circuit.add_input(0, 0);
+        let ab = circuit.add_mul(x[0], x[1]);
+        let ba = circuit.add_mul(x[1], duplicate);
+        let abc = circuit.add_mul(ab, x[2]);
+        let bac = circuit.add_mul(ba, x[2]);
+        let scaled = circuit.add_mul_by_const(abc, 2);
+        let other = circuit.add_mul_by_const(bac, 3);
+        circuit.set_roots(vec![abc, bac, scaled, other]);
+
+        let optimized = eliminate_common_subexpressions(&circuit);
+
+        // the inputs, a * b, (a * b) * c and both scalings
+        assert_eq!(optimized.size(), 7);
+        assert_eq!(optimized.get_roots(), vec![4, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_eliminate_dead_gates() {
+        let mut circuit = Circuit::new(3);
+        let x = inputs(&mut circuit);
+        let unused = circuit.add_mul(x[0], x[1]);
+        circuit.add_neg(unused);
+        let sum = circuit.add_add(x[0], x[2]);
+        circuit.set_roots(vec![sum]);
+
+        let optimized = eliminate_dead_gates(&circuit);
+
+        // the input of party 1 only feeds the unused product
+        assert_eq!(optimized.size(), 3);
+        assert_eq!(optimized.get_roots(), vec![2]);
+        assert!(matches!(optimized.get_gate(1), Gate::Input { party: 2, circuit_id: 0, output: _ }));
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let field = Field::new(ORDER);
+        let mut circuit = Circuit::new(3);
+        let x = inputs(&mut circuit);
+        let two = circuit.add_const(2);
+        let three = circuit.add_const(3);
+        let six = circuit.add_mul(two, three);
+        let scaled = circuit.add_mul(x[0], six);
+        let scaled = circuit.add_mul_by_const(scaled, 5);
+        let scaled = circuit.add_mul_by_const(scaled, 42);
+        let shifted = circuit.add_sub(scaled, three);
+        let unchanged = circuit.add_mul_by_const(x[1], 1);
+        circuit.set_roots(vec![scaled, shifted, unchanged]);
+
+        let optimized = eliminate_dead_gates(&fold_constants(&circuit, &field));
+
+        // 6 * 5 * 42 = 1260 = 5 mod 251
+        assert_eq!(optimized.size(), 4);
+        assert!(matches!(optimized.get_gate(2), Gate::MulByConst { first: 0, second: 5, output: _ }));
+        assert!(matches!(optimized.get_gate(3), Gate::AddConst { first: 2, second: 248, output: _ }));
+        assert_eq!(optimized.get_roots(), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut circuit = Circuit::new(3);
+        let x = inputs(&mut circuit);
+        let ab = circuit.add_mul(x[0], x[1]);
+        let ba = circuit.add_mul(x[1], x[0]);
+        let one = circuit.add_const(1);
+        let ab = circuit.add_mul(ab, one);
+        circuit.add_mul(ab, x[2]);
+        circuit.set_roots(vec![ab, ba]);
+
+        let (optimized, report) = optimize(&circuit, &Field::new(ORDER)).unwrap();
+
+        assert_eq!(optimized.size(), 3);
+        assert_eq!(report.to_string(), "8 -> 3 gates, 4 -> 1 multiplications");
+    }
+
+    #[test]
+    fn test_invalid_circuit() {
+        let mut circuit = Circuit::new(3);
+        circuit.add_neg(1);
+        circuit.add_input(0, 0);
+        assert!(matches!(
+            optimize(&circuit, &Field::new(ORDER)),
+            Err(CircuitError::ForwardReference { gate: 0, operand: 1 })
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn test_random_circuit(
+            ops in prop::collection::vec((0u8..8, any::<usize>(), any::<usize>(), 0..ORDER), 1..40),
+            secrets in prop::collection::vec(0..ORDER, 3),
+        ) {
+            let field = Field::new(ORDER);
+            let mut circuit = Circuit::new(3);
+            inputs(&mut circuit);
+            for (kind, first, second, constant) in ops {
+                let size = circuit.size();
+                let (first, second) = (first % size, second % size);
+                circuit.add(match kind {
+                    0 => Gate::new_add(first, second),
+                    1 => Gate::new_mul_by_const(first, constant % 3),
+                    2 => Gate::new_const(constant % 3),
+                    3 => Gate::new_add_const(first, constant),
+                    4 => Gate::new_sub(first, second),
+                    5 => Gate::new_neg(first),
+                    6 => Gate::new_input(first % 3, 0),
+                    _ => Gate::new_mul(first, second),
+                });
+            }
+            let size = circuit.size();
+            circuit.set_roots((size / 2..size).collect());
+
+            let (optimized, report) = optimize(&circuit, &field).unwrap();
+            let secrets: Vec<Vec<u16>> = secrets.into_iter().map(|secret| vec![secret]).collect();
+
+            prop_assert!(report.get_gates_after() <= report.get_gates_before());
+            prop_assert!(report.get_muls_after() <= report.get_muls_before());
+            prop_assert_eq!(evaluate(&optimized, &field, &secrets), evaluate(&circuit, &field, &secrets));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::io::ErrorKind;
+
+use crate::circuit::CircuitError;
+use crate::message::DecodeError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Disconnected,
+    Timeout,
+    InvalidCircuit(CircuitError),
+    MalformedFrame(DecodeError),
+    InconsistentShares {
+        round: usize,
+        // parties caught sending wrong shares so far, which may not be all of them
+        misbehaving: Vec<usize>
+    },
+    UnexpectedSender {
+        from: usize,
+        to: usize,
+        round: usize
+    },
+    PoolExhausted {
+        kind: &'static str,
+        needed: usize,
+        available: usize
+    },
+    WrongShareCount {
+        from: usize,
+        round: usize,
+        expected: usize,
+        actual: usize
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Disconnected => write!(f, "peer disconnected"),
+            Error::Timeout => write!(f, "timed out waiting for a share"),
+            Error::InvalidCircuit(e) => write!(f, "invalid circuit: {}", e),
+            Error::MalformedFrame(e) => write!(f, "malformed frame: {}", e),
+            Error::InconsistentShares { round, misbehaving } if misbehaving.is_empty() =>
+                write!(f, "too many wrong shares opened in round {} to correct", round),
+            Error::InconsistentShares { round, misbehaving } =>
+                write!(f, "too many wrong shares opened in round {} to correct, parties {:?} sent wrong shares", round, misbehaving),
+            Error::UnexpectedSender { from, to, round } =>
+                write!(f, "unexpected message from party {} to party {} for round {}", from, to, round),
+            Error::PoolExhausted { kind, needed, available } =>
+                write!(f, "preprocessing pool ran out: {} {} needed, {} available", needed, kind, available),
+            Error::WrongShareCount { from, round, expected, actual } =>
+                write!(f, "party {} sent {} shares for round {}, expected {}", from, actual, round, expected),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => Error::Timeout,
+            _ => Error::Disconnected,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::from(e.kind())
+    }
+}
+
+impl From<CircuitError> for Error {
+    fn from(e: CircuitError) -> Self {
+        Error::InvalidCircuit(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::Io(kind) => Error::from(kind),
+            e => Error::MalformedFrame(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use crate::message::DecodeError;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_from_decode_error() {
+        assert_eq!(Error::from(DecodeError::Io(ErrorKind::UnexpectedEof)), Error::Disconnected);
+        assert_eq!(Error::from(DecodeError::Io(ErrorKind::TimedOut)), Error::Timeout);
+        assert_eq!(Error::from(DecodeError::InvalidShare), Error::MalformedFrame(DecodeError::InvalidShare));
+    }
+}
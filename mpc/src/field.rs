@@ -1,40 +1,131 @@
-use std::ops::{Add, Sub, Mul, Rem};
-use rand::rngs::ThreadRng;
-use rand::Rng;
-use num::{Zero, One};
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Sub, Mul, Div, Rem, Neg};
+use rand::{Rng, RngCore, CryptoRng};
+use num::{Zero, One, BigUint};
+
+/// Arithmetic of a finite field, independent of how its elements are represented.
+///
+/// Implemented by the runtime `Field<DataType>`, which carries its order as a value,
+/// and by `PrimeField<P>`, whose order is part of the element type `Fp<P>`.
+pub trait FiniteField: Clone {
+    type Element: Clone + PartialEq + Debug + Display;
+
+    fn zero(&self) -> Self::Element;
+
+    fn one(&self) -> Self::Element;
+
+    fn add(&self, a: Self::Element, b: Self::Element) -> Self::Element;
+
+    fn sub(&self, a: Self::Element, b: Self::Element) -> Self::Element;
+
+    fn mul(&self, a: Self::Element, b: Self::Element) -> Self::Element;
+
+    fn inv(&self, a: Self::Element) -> Self::Element;
+
+    /// Samples a uniformly random element from `rng`.
+    fn random<R: RngCore + CryptoRng + ?Sized>(&self, rng: &mut R) -> Self::Element;
+
+    /// Bits of `order - 1`, most significant first. Every nonzero element raised to this
+    /// power is one, so a circuit can test for zero with multiplications only.
+    fn order_minus_one_bits(&self) -> Vec<bool>;
+
+    /// Embeds a small integer, such as a party's evaluation point, into the field.
+    fn element(&self, value: u16) -> Self::Element {
+        (0..16).rev().fold(self.zero(), |acc, bit| {
+            let acc = self.add(acc.clone(), acc);
+            if (value >> bit) & 1 == 1 {
+                self.add(acc, self.one())
+            } else {
+                acc
+            }
+        })
+    }
+}
 
 pub trait FieldElement: Add<Output = Self> +
                         Sub<Output = Self> +
                         Mul<Output = Self> +
+                        Div<Output = Self> +
                         Rem<Output = Self> +
                         PartialOrd +
                         Zero +
                         One +
                         Clone +
                         rand::distributions::uniform::SampleUniform
-where Self: Sized {}
-
-impl<T> FieldElement for T
-where T: Add<Output = T> +
-         Sub<Output = Self> +
-         Mul<Output = T> +
-         Rem<Output = T> +
-         PartialOrd +
-         Zero +
-         One +
-         Clone +
-         rand::distributions::uniform::SampleUniform {}
+where Self: Sized {
+    /// Type able to hold the sum and the product of any two values of `Self`,
+    /// so that arithmetic is reduced modulo the order before narrowing back.
+    type Wide: Add<Output = Self::Wide> +
+               Mul<Output = Self::Wide> +
+               Rem<Output = Self::Wide>;
+
+    fn widen(self) -> Self::Wide;
+
+    fn narrow(wide: Self::Wide) -> Self;
+}
+
+macro_rules! impl_field_element {
+    ($($t:ty => $wide:ty),*) => {
+        $(
+            impl FieldElement for $t {
+                type Wide = $wide;
+
+                fn widen(self) -> $wide {
+                    self as $wide
+                }
+
+                fn narrow(wide: $wide) -> Self {
+                    wide as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_field_element!(u8 => u16, u16 => u32, u32 => u64, u64 => u128);
+
+impl FieldElement for BigUint {
+    type Wide = BigUint;
+
+    fn widen(self) -> BigUint {
+        self
+    }
+
+    fn narrow(wide: BigUint) -> Self {
+        wide
+    }
+}
+
+/// Standard prime orders.
+pub mod primes {
+    use num::{BigUint, One};
+
+    pub const P251: u16 = 251;
+
+    /// 2^61 - 1
+    pub const MERSENNE_61: u64 = (1 << 61) - 1;
+
+    /// 2^127 - 1
+    pub fn mersenne_127() -> BigUint {
+        (BigUint::one() << 127usize) - BigUint::one()
+    }
+
+    /// Order of the prime subgroup of Curve25519: 2^252 + 27742317777372353535851937790883648493
+    pub fn curve25519_scalar() -> BigUint {
+        (BigUint::one() << 252usize) +
+            "27742317777372353535851937790883648493".parse::<BigUint>().unwrap()
+    }
+}
 
 #[derive(Clone)]
 pub struct Field<DataType> {
     order: DataType,
-    rand: ThreadRng,
 }
 
 impl<DataType: FieldElement> Field<DataType> {
 
     pub fn new(order: DataType) -> Self {
-        Field { order, rand: rand::thread_rng() }
+        Field { order }
     }
 
     pub(crate) fn zero(&self) -> DataType {
@@ -46,27 +137,43 @@ impl<DataType: FieldElement> Field<DataType> {
     }
 
     pub fn add(&self, a: DataType, b: DataType) -> DataType {
-        self.normalize(a + b)
+        self.reduce(a.widen() + b.widen())
     }
 
     pub fn sub(&self, a: DataType, b: DataType) -> DataType {
+        let (a, b) = (self.normalize(a), self.normalize(b));
         if a >= b {
-            self.normalize(a - b)
+            a - b
         } else {
-            self.order.clone() - self.normalize(b - a)
+            self.order.clone() - (b - a)
         }
     }
 
     pub fn mul(&self, a: DataType, b: DataType) -> DataType {
-        self.normalize(a * b)
+        self.reduce(a.widen() * b.widen())
     }
 
     pub fn inv(&self, a: DataType) -> DataType {
-        self.pow(a, self.order.clone() - self.one() - self.one())
+        // Extended Euclid on (order, a), tracking only the coefficient of a (mod order).
+        // NOTE: the order is assumed to be prime; the inverse of zero is zero
+        let (mut r0, mut r1) = (self.order.clone(), self.normalize(a));
+        let (mut t0, mut t1) = (self.zero(), self.one());
+
+        while r1 != self.zero() {
+            let q = r0.clone() / r1.clone();
+            let r2 = r0 - q.clone() * r1.clone();
+            let t2 = self.sub(t0, self.mul(self.normalize(q), t1.clone()));
+            r0 = r1;
+            r1 = r2;
+            t0 = t1;
+            t1 = t2;
+        }
+
+        t0
     }
 
-    pub fn random(&mut self) -> DataType {
-        self.rand.gen_range(DataType::zero()..self.order.clone())
+    pub fn random<R: RngCore + CryptoRng + ?Sized>(&self, rng: &mut R) -> DataType {
+        rng.gen_range(DataType::zero()..self.order.clone())
     }
 
     fn normalize(&self, a: DataType) -> DataType {
@@ -74,23 +181,316 @@ impl<DataType: FieldElement> Field<DataType> {
         a % self.order.clone()
     }
 
-    fn pow(&self, a: DataType, b: DataType) -> DataType {
+    fn reduce(&self, a: DataType::Wide) -> DataType {
+        DataType::narrow(a % self.order.clone().widen())
+    }
+
+    pub fn pow(&self, a: DataType, b: DataType) -> DataType {
+        let two = self.one() + self.one();
         let mut result = self.one();
-        
-        let mut counter = self.zero();
-        while counter != b {
-            result  = self.mul(result, a.clone());
-            counter = counter + self.one();
+        let mut base = self.normalize(a);
+        let mut exp = b;
+
+        while exp != self.zero() {
+            if exp.clone() % two.clone() == self.one() {
+                result = self.mul(result, base.clone());
+            }
+            base = self.mul(base.clone(), base);
+            exp = exp / two.clone();
+        }
+
+        result
+    }
+}
+
+impl<DataType: FieldElement + Debug + Display> FiniteField for Field<DataType> {
+    type Element = DataType;
+
+    fn zero(&self) -> DataType {
+        DataType::zero()
+    }
+
+    fn one(&self) -> DataType {
+        DataType::one()
+    }
+
+    fn add(&self, a: DataType, b: DataType) -> DataType {
+        Field::add(self, a, b)
+    }
+
+    fn sub(&self, a: DataType, b: DataType) -> DataType {
+        Field::sub(self, a, b)
+    }
+
+    fn mul(&self, a: DataType, b: DataType) -> DataType {
+        Field::mul(self, a, b)
+    }
+
+    fn inv(&self, a: DataType) -> DataType {
+        Field::inv(self, a)
+    }
+
+    fn random<R: RngCore + CryptoRng + ?Sized>(&self, rng: &mut R) -> DataType {
+        Field::random(self, rng)
+    }
+
+    fn order_minus_one_bits(&self) -> Vec<bool> {
+        let two = DataType::one() + DataType::one();
+        let mut exp = self.order.clone() - DataType::one();
+        let mut bits = Vec::new();
+        while exp != DataType::zero() {
+            bits.push(exp.clone() % two.clone() == DataType::one());
+            exp = exp / two.clone();
+        }
+        bits.reverse();
+        bits
+    }
+}
+
+/// Element of the prime field of order `P`, with the modulus fixed at compile time.
+///
+/// Values are kept reduced, and arithmetic goes through `u128`, so any `P < 2^64` works.
+/// Mixing elements of different fields is a type error.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Fp<const P: u64>(u64);
+
+impl<const P: u64> Fp<P> {
+
+    pub fn new(value: u64) -> Self {
+        Fp(value % P)
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    pub fn pow(self, mut exp: u64) -> Self {
+        let mut result = Fp(1 % P);
+        let mut base = self;
+
+        while exp != 0 {
+            if exp & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exp >>= 1;
+        }
+
+        result
+    }
+
+    /// Multiplicative inverse, by Fermat's little theorem; the inverse of zero is zero.
+    pub fn inv(self) -> Self {
+        self.pow(P - 2)
+    }
+}
+
+impl<const P: u64> From<u64> for Fp<P> {
+    fn from(value: u64) -> Self {
+        Fp::new(value)
+    }
+}
+
+impl<const P: u64> Display for Fp<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl<const P: u64> Add for Fp<P> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Fp(((self.0 as u128 + other.0 as u128) % P as u128) as u64)
+    }
+}
+
+impl<const P: u64> Sub for Fp<P> {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + (-other)
+    }
+}
+
+impl<const P: u64> Neg for Fp<P> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Fp((P - self.0) % P)
+    }
+}
+
+impl<const P: u64> Mul for Fp<P> {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Fp(((self.0 as u128 * other.0 as u128) % P as u128) as u64)
+    }
+}
+
+impl<const P: u64> Div for Fp<P> {
+    type Output = Self;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: Self) -> Self {
+        self * other.inv()
+    }
+}
+
+/// The prime field of order `P`; a zero-sized handle for working with `Fp<P>`
+/// wherever a `FiniteField` is expected.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PrimeField<const P: u64>;
+
+impl<const P: u64> FiniteField for PrimeField<P> {
+    type Element = Fp<P>;
+
+    fn zero(&self) -> Fp<P> {
+        Fp(0)
+    }
+
+    fn one(&self) -> Fp<P> {
+        Fp::new(1)
+    }
+
+    fn add(&self, a: Fp<P>, b: Fp<P>) -> Fp<P> {
+        a + b
+    }
+
+    fn sub(&self, a: Fp<P>, b: Fp<P>) -> Fp<P> {
+        a - b
+    }
+
+    fn mul(&self, a: Fp<P>, b: Fp<P>) -> Fp<P> {
+        a * b
+    }
+
+    fn inv(&self, a: Fp<P>) -> Fp<P> {
+        a.inv()
+    }
+
+    fn random<R: RngCore + CryptoRng + ?Sized>(&self, rng: &mut R) -> Fp<P> {
+        Fp(rng.gen_range(0..P))
+    }
+
+    fn element(&self, value: u16) -> Fp<P> {
+        Fp::new(value as u64)
+    }
+
+    fn order_minus_one_bits(&self) -> Vec<bool> {
+        (0..64 - (P - 1).leading_zeros()).rev().map(|bit| ((P - 1) >> bit) & 1 == 1).collect()
+    }
+}
+
+/// The binary extension field GF(2^k), with elements stored as bit patterns of
+/// polynomials over GF(2) of degree below k.
+///
+/// Addition and subtraction are XOR, so on {0, 1} `Add` computes XOR and `Mul` computes AND.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BinaryField {
+    degree: u32,
+    modulus: u64,
+}
+
+impl BinaryField {
+
+    /// GF(2^degree) reduced by `modulus`, an irreducible polynomial of the given degree
+    /// (bit i holds the coefficient of x^i, including the leading term).
+    pub fn new(degree: u32, modulus: u64) -> Self {
+        assert!(0 < degree && degree < 64, "degree must be between 1 and 63");
+        assert_eq!(modulus >> degree, 1, "modulus must have degree {}", degree);
+        BinaryField { degree, modulus }
+    }
+
+    /// GF(2^8) with the AES polynomial x^8 + x^4 + x^3 + x + 1.
+    pub fn gf256() -> Self {
+        BinaryField::new(8, 0x11b)
+    }
+
+    /// GF(2^16) with x^16 + x^5 + x^3 + x^2 + 1.
+    pub fn gf65536() -> Self {
+        BinaryField::new(16, 0x1002d)
+    }
+
+    pub fn pow(&self, a: u64, mut exp: u64) -> u64 {
+        let mut result = 1;
+        let mut base = a;
+
+        while exp != 0 {
+            if exp & 1 == 1 {
+                result = FiniteField::mul(self, result, base);
+            }
+            base = FiniteField::mul(self, base, base);
+            exp >>= 1;
         }
 
         result
     }
 }
 
+impl FiniteField for BinaryField {
+    type Element = u64;
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn one(&self) -> u64 {
+        1
+    }
+
+    fn add(&self, a: u64, b: u64) -> u64 {
+        a ^ b
+    }
+
+    fn sub(&self, a: u64, b: u64) -> u64 {
+        a ^ b
+    }
+
+    fn mul(&self, a: u64, b: u64) -> u64 {
+        // Shift-and-add, reducing a by the modulus whenever it reaches degree k
+        let (mut a, mut b) = (a, b);
+        let mut result = 0;
+
+        while b != 0 {
+            if b & 1 == 1 {
+                result ^= a;
+            }
+            b >>= 1;
+            a <<= 1;
+            if (a >> self.degree) & 1 == 1 {
+                a ^= self.modulus;
+            }
+        }
+
+        result
+    }
+
+    fn inv(&self, a: u64) -> u64 {
+        // The multiplicative group has order 2^k - 1; the inverse of zero is zero
+        self.pow(a, (1 << self.degree) - 2)
+    }
+
+    fn random<R: RngCore + CryptoRng + ?Sized>(&self, rng: &mut R) -> u64 {
+        rng.gen_range(0..1 << self.degree)
+    }
+
+    fn element(&self, value: u16) -> u64 {
+        // NOTE: integers are embedded by their bit pattern, which keeps distinct
+        // evaluation points distinct as long as they fit in k bits
+        assert!((value as u64) >> self.degree == 0, "{} does not fit in GF(2^{})", value, self.degree);
+        value as u64
+    }
+
+    fn order_minus_one_bits(&self) -> Vec<bool> {
+        vec![true; self.degree as usize]
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::Field;
+    use super::{Field, Fp, PrimeField, BinaryField, FiniteField};
 
     #[test]
     fn test_zero() {
@@ -128,9 +528,194 @@ mod tests {
         assert_eq!(field.inv(7u8), 2u8);
     }
 
+    #[test]
+    fn test_inv_all() {
+        let field = Field::new(251u16);
+        for a in 1..251u16 {
+            assert_eq!(field.mul(a, field.inv(a)), 1u16);
+        }
+        assert_eq!(field.inv(0u16), 0u16);
+    }
+
+    #[test]
+    fn test_inv_large() {
+        let field = Field::new(2147483647u64);
+        for &a in &[2u64, 3, 1234567, 2147483646] {
+            assert_eq!(field.mul(a, field.inv(a)), 1u64);
+        }
+    }
+
+    #[test]
+    fn test_pow() {
+        let field = Field::new(13u8);
+        assert_eq!(field.pow(2u8, 0u8), 1u8);
+        assert_eq!(field.pow(2u8, 5u8), 6u8);
+        assert_eq!(field.pow(7u8, 12u8), 1u8);
+    }
+
+    #[test]
+    fn test_pow_large() {
+        let field = Field::new(2147483647u64);
+        assert_eq!(field.pow(16807u64, 2147483646u64), 1u64);
+        assert_eq!(field.pow(2u64, 31u64), 1u64);
+    }
+
     #[test]
     fn test_random() {
-        let mut field = Field::new(13u8);
-        assert!(field.random() < 13u8);
+        let field = Field::new(13u8);
+        assert!(field.random(&mut rand::thread_rng()) < 13u8);
+    }
+
+    macro_rules! sweep_moduli {
+        ($name:ident, $t:ty, [$($order:expr),*]) => {
+            #[test]
+            fn $name() {
+                $(
+                    let order: $t = $order;
+                    let field = Field::new(order);
+                    let values = [0, 1, 2, order / 2, order - 2, order - 1];
+                    for &a in values.iter() {
+                        for &b in values.iter() {
+                            let (wa, wb, wo) = (a as u128, b as u128, order as u128);
+                            assert_eq!(field.add(a, b) as u128, (wa + wb) % wo);
+                            assert_eq!(field.sub(a, b) as u128, (wa + wo - wb) % wo);
+                            assert_eq!(field.mul(a, b) as u128, (wa * wb) % wo);
+                        }
+                        if a != 0 {
+                            assert_eq!(field.mul(a, field.inv(a)), 1);
+                        }
+                    }
+                )*
+            }
+        };
+    }
+
+    sweep_moduli!(test_sweep_u8, u8, [251, 241]);
+    sweep_moduli!(test_sweep_u16, u16, [65521, 65519]);
+    sweep_moduli!(test_sweep_u32, u32, [4294967291, 4294967279]);
+    sweep_moduli!(test_sweep_u64, u64, [18446744073709551557, 18446744073709551533, 2305843009213693951]);
+
+    #[test]
+    fn test_big_field() {
+        use super::primes::{mersenne_127, curve25519_scalar};
+        use num::{BigUint, One};
+
+        for order in [mersenne_127(), curve25519_scalar()] {
+            let field = Field::new(order.clone());
+            let a = order.clone() - BigUint::one();
+            let b: BigUint = order.clone() >> 1usize;
+            assert_eq!(field.add(a.clone(), a.clone()), order.clone() - 2u32);
+            assert_eq!(field.mul(a.clone(), a.clone()), BigUint::one());
+            assert_eq!(field.mul(b.clone(), field.inv(b.clone())), BigUint::one());
+            assert_eq!(field.sub(BigUint::one(), a.clone()), BigUint::from(2u32));
+            assert!(field.random(&mut rand::thread_rng()) < order);
+        }
+    }
+
+    #[test]
+    fn test_primes() {
+        use super::primes::{MERSENNE_61, mersenne_127, curve25519_scalar};
+        use num::BigUint;
+
+        assert_eq!(MERSENNE_61, 2305843009213693951);
+        assert_eq!(mersenne_127(), "170141183460469231731687303715884105727".parse::<BigUint>().unwrap());
+        assert_eq!(curve25519_scalar(), "7237005577332262213973186563042994240857116359379907606001950938285454250989".parse::<BigUint>().unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_fp() {
+        type F13 = Fp<13>;
+        assert_eq!(F13::new(7) + F13::new(8), F13::new(2));
+        assert_eq!(F13::new(7) - F13::new(8), F13::new(12));
+        assert_eq!(F13::new(7) * F13::new(8), F13::new(4));
+        assert_eq!(-F13::new(0), F13::new(0));
+        assert_eq!(-F13::new(1), F13::new(12));
+        assert_eq!(F13::new(1) / F13::new(7), F13::new(2));
+        assert_eq!(F13::new(20).value(), 7);
+    }
+
+    #[test]
+    fn test_fp_near_max() {
+        type F = Fp<18446744073709551557>;
+        let a = F::new(18446744073709551556);
+        assert_eq!(a + a, F::new(18446744073709551555));
+        assert_eq!(a * a, F::new(1));
+        assert_eq!(a * a.inv(), F::new(1));
+    }
+
+    #[test]
+    fn test_prime_field_matches_field() {
+        let fp = PrimeField::<251>;
+        let field = Field::new(251u64);
+        for &(a, b) in &[(0u64, 0u64), (1, 250), (250, 250), (17, 201)] {
+            assert_eq!(fp.add(Fp::new(a), Fp::new(b)).value(), field.add(a, b));
+            assert_eq!(fp.sub(Fp::new(a), Fp::new(b)).value(), field.sub(a, b));
+            assert_eq!(fp.mul(Fp::new(a), Fp::new(b)).value(), field.mul(a, b));
+            assert_eq!(fp.inv(Fp::new(a)).value(), field.inv(a));
+        }
+        assert_eq!(fp.element(300).value(), 49);
+        assert_eq!(FiniteField::element(&field, 300), 49);
+        assert!(fp.random(&mut rand::thread_rng()).value() < 251);
+    }
+
+    #[test]
+    fn test_binary_field() {
+        let field = BinaryField::gf256();
+        assert_eq!(field.add(0x57, 0x83), 0xd4);
+        assert_eq!(field.sub(0x57, 0x83), 0xd4);
+        assert_eq!(field.mul(0x57, 0x83), 0xc1);
+        assert_eq!(field.mul(0x57, 0x13), 0xfe);
+        assert_eq!(field.inv(0x53), 0xca);
+        assert_eq!(field.inv(0), 0);
+        assert_eq!(field.element(5), 5);
+        assert!(field.random(&mut rand::thread_rng()) < 256);
+    }
+
+    #[test]
+    fn test_binary_field_boolean() {
+        let field = BinaryField::gf256();
+        for &a in &[0, 1] {
+            for &b in &[0, 1] {
+                assert_eq!(field.add(a, b), a ^ b);
+                assert_eq!(field.mul(a, b), a & b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_field_inv_all() {
+        for field in [BinaryField::gf256(), BinaryField::gf65536()] {
+            for a in 1..(1u64 << field.degree) {
+                assert_eq!(field.mul(a, field.inv(a)), 1);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_binary_field_element_overflow() {
+        BinaryField::gf256().element(256);
+    }
+
+    fn fermat<F: FiniteField>(field: &F, a: F::Element) -> F::Element {
+        field.order_minus_one_bits().into_iter().fold(field.one(), |acc, bit| {
+            let acc = field.mul(acc.clone(), acc);
+            if bit { field.mul(acc, a.clone()) } else { acc }
+        })
+    }
+
+    #[test]
+    fn test_order_minus_one_bits() {
+        assert_eq!(Field::new(251u16).order_minus_one_bits(), vec![true, true, true, true, true, false, true, false]);
+        assert_eq!(PrimeField::<251>.order_minus_one_bits(), Field::new(251u16).order_minus_one_bits());
+        assert_eq!(BinaryField::gf256().order_minus_one_bits(), vec![true; 8]);
+
+        for a in 1..251 {
+            assert_eq!(fermat(&Field::new(251u16), a), 1);
+        }
+        let big = Field::new(super::primes::curve25519_scalar());
+        assert_eq!(fermat(&big, num::BigUint::from(u64::MAX)), num::BigUint::from(1u32));
+        assert_eq!(fermat(&big, num::BigUint::from(0u32)), num::BigUint::from(0u32));
+        assert_eq!(fermat(&BinaryField::gf256(), 0x53), 1);
+    }
+}
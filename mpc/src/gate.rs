@@ -12,6 +12,23 @@ pub enum Gate<DataType: Clone> {
         output: Option<DataType>
     },
 
+    AddConst {
+        first: usize,
+        second: DataType,
+        output: Option<DataType>
+    },
+
+    Sub {
+        first: usize,
+        second: usize,
+        output: Option<DataType>
+    },
+
+    Neg {
+        first: usize,
+        output: Option<DataType>
+    },
+
     Mul {
         first: usize,
         second: usize,
@@ -22,6 +39,11 @@ pub enum Gate<DataType: Clone> {
         party: usize,
         circuit_id: usize,
         output: Option<DataType>
+    },
+
+    Const {
+        value: DataType,
+        output: Option<DataType>
     }
 }
 
@@ -35,6 +57,13 @@ impl<DataType: Clone> Gate<DataType> {
         }
     }
 
+    pub fn new_const(value: DataType) -> Self {
+        Gate::Const {
+            value,
+            output: None
+        }
+    }
+
     pub fn new_add(first: usize, second: usize) -> Self {
         Gate::Add {
             first, second,
@@ -49,6 +78,27 @@ impl<DataType: Clone> Gate<DataType> {
         }
     }
 
+    pub fn new_add_const(first: usize, second: DataType) -> Self {
+        Gate::AddConst {
+            first, second,
+            output: None
+        }
+    }
+
+    pub fn new_sub(first: usize, second: usize) -> Self {
+        Gate::Sub {
+            first, second,
+            output: None
+        }
+    }
+
+    pub fn new_neg(first: usize) -> Self {
+        Gate::Neg {
+            first,
+            output: None
+        }
+    }
+
     pub fn new_mul(first: usize, second: usize) -> Self {
         Gate::Mul {
             first, second,
@@ -61,7 +111,25 @@ impl<DataType: Clone> Gate<DataType> {
             Gate::Input { party: _, circuit_id: _, output } => (*output).clone().unwrap(),
             Gate::Add { first: _, second: _, output } => (*output).clone().unwrap(),
             Gate::MulByConst {first: _, second: _, output } => (*output).clone().unwrap(),
-            Gate::Mul { first: _, second: _, output } => (*output).clone().unwrap()
+            Gate::AddConst { first: _, second: _, output } => (*output).clone().unwrap(),
+            Gate::Sub { first: _, second: _, output } => (*output).clone().unwrap(),
+            Gate::Neg { first: _, output } => (*output).clone().unwrap(),
+            Gate::Mul { first: _, second: _, output } => (*output).clone().unwrap(),
+            Gate::Const { value: _, output } => (*output).clone().unwrap()
+        }
+    }
+
+    /// Ids of the gates whose outputs this gate reads.
+    pub fn get_operands(&self) -> Vec<usize> {
+        match self {
+            Gate::Input { party: _, circuit_id: _, output: _ } => vec![],
+            Gate::Add { first, second, output: _ } => vec![*first, *second],
+            Gate::MulByConst { first, second: _, output: _ } => vec![*first],
+            Gate::AddConst { first, second: _, output: _ } => vec![*first],
+            Gate::Sub { first, second, output: _ } => vec![*first, *second],
+            Gate::Neg { first, output: _ } => vec![*first],
+            Gate::Mul { first, second, output: _ } => vec![*first, *second],
+            Gate::Const { value: _, output: _ } => vec![]
         }
     }
 
@@ -70,7 +138,11 @@ impl<DataType: Clone> Gate<DataType> {
             Gate::Input { party: _, circuit_id: _, output } => *output = Some(value),
             Gate::Add { first: _, second: _, output } => *output = Some(value),
             Gate::MulByConst {first: _, second: _, output } => *output = Some(value),
-            Gate::Mul { first: _, second: _, output } => *output = Some(value)
+            Gate::AddConst { first: _, second: _, output } => *output = Some(value),
+            Gate::Sub { first: _, second: _, output } => *output = Some(value),
+            Gate::Neg { first: _, output } => *output = Some(value),
+            Gate::Mul { first: _, second: _, output } => *output = Some(value),
+            Gate::Const { value: _, output } => *output = Some(value)
         }
     }
 }
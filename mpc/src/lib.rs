@@ -1,8 +1,18 @@
 pub mod party;
 pub mod circuit;
+pub mod builder;
 pub mod gate;
 pub mod field;
 pub mod share_receiver;
 pub mod share_sender;
 pub mod message;
+pub mod channel;
+pub mod preprocessing;
+pub mod multiplication;
+pub mod vss;
+pub mod testing;
+pub mod rng;
 mod polynomial;
+mod error;
+
+pub use error::Error;
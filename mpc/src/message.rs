@@ -1,18 +1,98 @@
+use std::convert::TryInto;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::io::Read;
+
+/// Version of the wire format produced by `Message::encode`.
+pub const WIRE_VERSION: u8 = 2;
+
+/// Upper bound on the body length accepted by `Message::decode`.
+pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+pub(crate) const LEN_PREFIX: usize = std::mem::size_of::<u32>();
+
+// version (u8) | from (u32) | to (u32) | round (u64) | share count (u32)
+const HEADER_LEN: usize = 1 + 4 + 4 + 8 + 4;
+
+/// Conversion of a share value to and from its byte representation on the wire.
+pub trait WireShare: Sized {
+    fn to_wire(&self) -> Vec<u8>;
+    fn from_wire(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_wire_share {
+    ($($t:ty),*) => {
+        $(
+            impl WireShare for $t {
+                fn to_wire(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+
+                fn from_wire(bytes: &[u8]) -> Option<Self> {
+                    bytes.try_into().ok().map(<$t>::from_be_bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_wire_share!(u8, u16, u32, u64, u128);
+
+impl WireShare for num::BigUint {
+    fn to_wire(&self) -> Vec<u8> {
+        self.to_bytes_be()
+    }
+
+    fn from_wire(bytes: &[u8]) -> Option<Self> {
+        Some(num::BigUint::from_bytes_be(bytes))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    Io(std::io::ErrorKind),
+    Truncated { expected: usize, actual: usize },
+    FrameTooLarge(usize),
+    UnsupportedVersion(u8),
+    UnsupportedPoolVersion(u8),
+    FieldOverflow(&'static str),
+    InvalidShare,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Io(kind) => write!(f, "i/o error while reading frame: {:?}", kind),
+            DecodeError::Truncated { expected, actual } =>
+                write!(f, "truncated frame: expected {} bytes, got {}", expected, actual),
+            DecodeError::FrameTooLarge(len) =>
+                write!(f, "frame of {} bytes exceeds the limit of {} bytes", len, MAX_FRAME_LEN),
+            DecodeError::UnsupportedVersion(version) =>
+                write!(f, "unsupported wire version {} (expected {})", version, WIRE_VERSION),
+            DecodeError::UnsupportedPoolVersion(version) =>
+                write!(f, "unsupported pool version {} (expected {})", version, crate::preprocessing::POOL_VERSION),
+            DecodeError::FieldOverflow(field) => write!(f, "field `{}` does not fit in usize", field),
+            DecodeError::InvalidShare => write!(f, "share bytes do not encode a valid value"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
 
 #[derive(Clone)]
 pub struct Message<DataType> {
     from: usize,
     to: usize,
-    gate: usize,
-    share: DataType
+    round: usize,
+    shares: Vec<DataType>
 }
 
 impl<DataType: Clone> Message<DataType> {
 
-    pub fn new(from: usize, to: usize, gate: usize, share: DataType) -> Self {
+    /// All shares `from` sends to `to` in `round`, in the order both parties evaluate them.
+    pub fn new(from: usize, to: usize, round: usize, shares: Vec<DataType>) -> Self {
         Message {
-            from, to, gate, share
+            from, to, round, shares
         }
     }
 
@@ -24,21 +104,111 @@ impl<DataType: Clone> Message<DataType> {
         self.to
     }
 
-    pub(crate) fn get_gate(&self) -> usize {
-        self.gate
+    pub(crate) fn get_round(&self) -> usize {
+        self.round
     }
 
-    pub(crate) fn get_share(&self) -> DataType {
-        self.share.clone()
+    pub(crate) fn get_shares(&self) -> Vec<DataType> {
+        self.shares.clone()
     }
 }
 
+impl<DataType: WireShare> Message<DataType> {
+
+    /// Encodes the message as a length-prefixed frame: a big-endian `u32` body length
+    /// followed by the version, `from`, `to`, `round`, the number of shares and the
+    /// length-prefixed bytes of each share.
+    pub fn encode(&self) -> Vec<u8> {
+        let shares: Vec<Vec<u8>> = self.shares.iter().map(WireShare::to_wire).collect();
+        let body_len = HEADER_LEN + shares.iter().map(|share| LEN_PREFIX + share.len()).sum::<usize>();
+
+        let mut frame = Vec::with_capacity(LEN_PREFIX + body_len);
+        frame.extend_from_slice(&(body_len as u32).to_be_bytes());
+        frame.push(WIRE_VERSION);
+        frame.extend_from_slice(&(self.from as u32).to_be_bytes());
+        frame.extend_from_slice(&(self.to as u32).to_be_bytes());
+        frame.extend_from_slice(&(self.round as u64).to_be_bytes());
+        frame.extend_from_slice(&(shares.len() as u32).to_be_bytes());
+        for share in shares {
+            frame.extend_from_slice(&(share.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&share);
+        }
+        frame
+    }
+
+    /// Decodes a complete frame, as produced by `encode`.
+    pub fn decode(frame: &[u8]) -> Result<Self, DecodeError> {
+        let body_len = Self::body_len(take(frame, 0, LEN_PREFIX)?)?;
+        let body = take(frame, LEN_PREFIX, body_len)?;
+        if frame.len() > LEN_PREFIX + body_len {
+            return Err(DecodeError::Truncated { expected: LEN_PREFIX + body_len, actual: frame.len() });
+        }
+        Self::decode_body(body)
+    }
+
+    /// Reads exactly one frame from `reader` and decodes it.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, DecodeError> {
+        let mut prefix = [0u8; LEN_PREFIX];
+        reader.read_exact(&mut prefix).map_err(|e| DecodeError::Io(e.kind()))?;
+
+        let mut body = vec![0u8; Self::body_len(&prefix)?];
+        reader.read_exact(&mut body).map_err(|e| DecodeError::Io(e.kind()))?;
+
+        Self::decode_body(&body)
+    }
+
+    fn body_len(prefix: &[u8]) -> Result<usize, DecodeError> {
+        let len = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+        if len > MAX_FRAME_LEN {
+            Err(DecodeError::FrameTooLarge(len))
+        } else {
+            Ok(len)
+        }
+    }
+
+    fn decode_body(body: &[u8]) -> Result<Self, DecodeError> {
+        let version = take(body, 0, 1)?[0];
+        if version != WIRE_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let from = u32::from_be_bytes(take(body, 1, 4)?.try_into().unwrap());
+        let to = u32::from_be_bytes(take(body, 5, 4)?.try_into().unwrap());
+        let round = u64::from_be_bytes(take(body, 9, 8)?.try_into().unwrap());
+        let n_shares = u32::from_be_bytes(take(body, 17, 4)?.try_into().unwrap()) as usize;
+
+        // NOTE: `n_shares` is untrusted, so the vector grows with the shares actually present
+        let mut shares = Vec::new();
+        let mut offset = HEADER_LEN;
+        for _ in 0..n_shares {
+            let share_len = u32::from_be_bytes(take(body, offset, LEN_PREFIX)?.try_into().unwrap()) as usize;
+            let share = take(body, offset + LEN_PREFIX, share_len)?;
+            shares.push(DataType::from_wire(share).ok_or(DecodeError::InvalidShare)?);
+            offset += LEN_PREFIX + share_len;
+        }
+        if body.len() != offset {
+            return Err(DecodeError::Truncated { expected: offset, actual: body.len() });
+        }
+
+        Ok(Message {
+            from: from as usize,
+            to: to as usize,
+            round: round.try_into().map_err(|_| DecodeError::FieldOverflow("round"))?,
+            shares
+        })
+    }
+}
+
+pub(crate) fn take(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], DecodeError> {
+    bytes.get(offset..offset + len)
+        .ok_or(DecodeError::Truncated { expected: offset + len, actual: bytes.len() })
+}
+
 impl<DataType> PartialEq for Message<DataType> {
     fn eq(&self, other: &Self) -> bool {
-        return
-            self.from.eq(&other.from) &&
-            self.to.eq(&other.to) &&
-            self.gate.eq(&other.gate);
+        self.from.eq(&other.from) &&
+        self.to.eq(&other.to) &&
+        self.round.eq(&other.round)
     }
 }
 
@@ -48,6 +218,79 @@ impl<DataType> Hash for Message<DataType> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.from.hash(state);
         self.to.hash(state);
-        self.gate.hash(state);
+        self.round.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Message, DecodeError, WIRE_VERSION};
+
+    #[test]
+    fn test_round_trip() {
+        let msg = Message::new(3, 7, 1024, vec![250u16, 0, 7]);
+        let decoded = Message::<u16>::decode(&msg.encode()).unwrap();
+        assert_eq!(decoded.get_from(), 3);
+        assert_eq!(decoded.get_to(), 7);
+        assert_eq!(decoded.get_round(), 1024);
+        assert_eq!(decoded.get_shares(), vec![250u16, 0, 7]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_round_trip_empty() {
+        let decoded = Message::<u16>::decode(&Message::<u16>::new(0, 1, 2, vec![]).encode()).unwrap();
+        assert_eq!(decoded.get_shares(), vec![]);
+    }
+
+    #[test]
+    fn test_round_trip_big() {
+        // BigUint shares have varying widths
+        let share = crate::field::primes::curve25519_scalar() - 1u32;
+        let shares = vec![share, num::BigUint::from(1u32), num::BigUint::from(0u32)];
+        let decoded = Message::<num::BigUint>::decode(&Message::new(0, 1, 2, shares.clone()).encode()).unwrap();
+        assert_eq!(decoded.get_shares(), shares);
+    }
+
+    #[test]
+    fn test_read_from() {
+        let frames = [Message::new(0, 1, 2, vec![3u64]).encode(), Message::new(1, 0, 5, vec![8u64, 9]).encode()].concat();
+        let mut reader = &frames[..];
+        assert_eq!(Message::<u64>::read_from(&mut reader).unwrap().get_round(), 2);
+        assert_eq!(Message::<u64>::read_from(&mut reader).unwrap().get_shares(), vec![8u64, 9]);
+        assert_eq!(Message::<u64>::read_from(&mut reader).err(), Some(DecodeError::Io(std::io::ErrorKind::UnexpectedEof)));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let frame = Message::new(0, 1, 2, vec![3u16, 4]).encode();
+        assert!(matches!(Message::<u16>::decode(&frame[..frame.len() - 1]), Err(DecodeError::Truncated { .. })));
+        assert!(matches!(Message::<u16>::decode(&frame[..2]), Err(DecodeError::Truncated { .. })));
+    }
+
+    #[test]
+    fn test_wrong_share_count() {
+        // the last byte of the share count claims a third share which is not there
+        let mut frame = Message::new(0, 1, 2, vec![3u16, 4]).encode();
+        frame[4 + 20] = 3;
+        assert!(matches!(Message::<u16>::decode(&frame), Err(DecodeError::Truncated { .. })));
+    }
+
+    #[test]
+    fn test_bad_version() {
+        let mut frame = Message::new(0, 1, 2, vec![3u16]).encode();
+        frame[4] = WIRE_VERSION + 1;
+        assert_eq!(Message::<u16>::decode(&frame).err(), Some(DecodeError::UnsupportedVersion(WIRE_VERSION + 1)));
+    }
+
+    #[test]
+    fn test_wrong_share_width() {
+        let frame = Message::new(0, 1, 2, vec![3u32]).encode();
+        assert_eq!(Message::<u16>::decode(&frame).err(), Some(DecodeError::InvalidShare));
+    }
+
+    #[test]
+    fn test_frame_too_large() {
+        let frame = (u32::MAX).to_be_bytes();
+        assert!(matches!(Message::<u16>::decode(&frame), Err(DecodeError::FrameTooLarge(_))));
+    }
+}
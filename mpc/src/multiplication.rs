@@ -0,0 +1,139 @@
+use crate::{field, party, preprocessing, Error};
+
+/// A protocol multiplying shared values, used by `Party` for the `Mul` gates of each layer
+/// of the circuit and selected with `Party::with_multiplication`.
+pub trait Multiplication<F: field::FiniteField> {
+    /// Numbers of double sharings and Beaver triples consumed by `n` multiplications.
+    fn randomness(&self, n: usize) -> (usize, usize);
+
+    /// Returns shares of degree t of x * y for every pair of shares (x, y) of degree t. Every
+    /// party calls it with the same number of pairs, and it should take a single round.
+    fn multiply(&self, ctx: &mut Context<F>, pairs: Vec<(F::Element, F::Element)>) -> Result<Vec<F::Element>, Error>;
+}
+
+/// What a `Multiplication` can do on behalf of its party: read the field, take randomness
+/// reserved for it and open values.
+pub struct Context<'a, F: field::FiniteField> {
+    party: &'a mut party::Party<F>,
+    pool: &'a mut preprocessing::Pool<F::Element>,
+}
+
+impl<'a, F: field::FiniteField> Context<'a, F> {
+
+    pub(crate) fn new(party: &'a mut party::Party<F>, pool: &'a mut preprocessing::Pool<F::Element>) -> Self {
+        Context { party, pool }
+    }
+
+    pub fn field(&self) -> &F {
+        self.party.get_field()
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.party.get_threshold()
+    }
+
+    pub fn take_double_sharings(&mut self, n: usize) -> Result<Vec<preprocessing::DoubleSharing<F::Element>>, Error> {
+        self.pool.take_double_sharings(n)
+    }
+
+    pub fn take_triples(&mut self, n: usize) -> Result<Vec<preprocessing::Triple<F::Element>>, Error> {
+        self.pool.take_triples(n)
+    }
+
+    /// Runs a round in which every party sends its `shares`, of degree `degree`, to all
+    /// others, and returns the values they open. Each is decoded from the shares received
+    /// so far once degree + t + 1 of them agree, or all n if fewer, waiting for more until
+    /// they do, so up to t wrong ones are corrected with n >= degree + 2t + 1 parties.
+    pub fn open(&mut self, shares: Vec<F::Element>, degree: usize) -> Result<Vec<F::Element>, Error> {
+        self.party.open(shares, degree)
+    }
+}
+
+/// Degree reduction with double sharings: masks the product of the shares, of degree 2t,
+/// with r of degree 2t, opens it and subtracts r of degree t.
+///
+/// Opens one value per multiplication, but reconstructing a degree 2t sharing needs the
+/// shares of at least 2t + 1 parties, and correcting t wrong ones 4t + 1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DegreeReduction;
+
+impl<F: field::FiniteField> Multiplication<F> for DegreeReduction {
+    fn randomness(&self, n: usize) -> (usize, usize) {
+        (n, 0)
+    }
+
+    fn multiply(&self, ctx: &mut Context<F>, pairs: Vec<(F::Element, F::Element)>) -> Result<Vec<F::Element>, Error> {
+        let masks = ctx.take_double_sharings(pairs.len())?;
+        let field = ctx.field().clone();
+
+        let g_shares = pairs.into_iter().zip(&masks)
+            .map(|((x, y), (_, t))| field.add(field.mul(x, y), t.clone()))
+            .collect();
+
+        let degree = 2 * ctx.threshold();
+        Ok(ctx.open(g_shares, degree)?.into_iter().zip(masks)
+            .map(|(g, (s, _))| field.sub(g, s))
+            .collect())
+    }
+}
+
+/// Beaver's multiplication with a triple a, b, c = a * b: opens d = x - a and e = y - b,
+/// then x * y = c + d * b + e * a + d * e.
+///
+/// Opens two values per multiplication, all of degree t, so t wrong shares are corrected
+/// with 3t + 1 parties, and leaves the costly part to the preprocessing of the triples.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Beaver;
+
+impl<F: field::FiniteField> Multiplication<F> for Beaver {
+    fn randomness(&self, n: usize) -> (usize, usize) {
+        (0, n)
+    }
+
+    fn multiply(&self, ctx: &mut Context<F>, pairs: Vec<(F::Element, F::Element)>) -> Result<Vec<F::Element>, Error> {
+        let triples = ctx.take_triples(pairs.len())?;
+        let field = ctx.field().clone();
+
+        let masked = pairs.into_iter().zip(&triples)
+            .flat_map(|((x, y), (a, b, _))| vec![field.sub(x, a.clone()), field.sub(y, b.clone())])
+            .collect();
+        let degree = ctx.threshold();
+        let opened = ctx.open(masked, degree)?;
+
+        Ok(triples.into_iter().zip(opened.chunks(2))
+            .map(|((a, b, c), de)| {
+                let (d, e) = (de[0].clone(), de[1].clone());
+                // NOTE: d * e is public, so adding it to every share adds it to the secret
+                let z = field.add(c, field.mul(d.clone(), b));
+                let z = field.add(z, field.mul(e.clone(), a));
+                field.add(z, field.mul(d, e))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Beaver;
+    use crate::{channel::{spawn_parties, tests::{mul_circuit, transcript_with}}, field::Field};
+
+    #[test]
+    fn test_run_beaver() {
+        let secrets = (0..3).map(|id| vec![id as u16 + 3]).collect();
+        let outputs = spawn_parties(Field::new(251u16), mul_circuit(), secrets, 1, |_, tx| Box::new(tx), |_, party| {
+            party.with_multiplication(Beaver).setup()?.run()
+        });
+
+        assert_eq!(outputs, vec![Ok(vec![60]); 3]);
+    }
+
+    #[test]
+    fn test_beaver_rounds() {
+        // the setup deals a, b and a double sharing for each of the two triples and reduces
+        // a * b in a second round; then every multiplication opens d and e instead of one value
+        for messages in transcript_with(mul_circuit(), 1, Beaver) {
+            let rounds: Vec<(usize, usize)> = messages.iter().map(|(_, round, shares)| (*round, shares.len())).collect();
+            assert_eq!(rounds, vec![(0, 8), (0, 8), (1, 2), (1, 2), (2, 1), (2, 1), (3, 2), (3, 2), (4, 2), (4, 2), (5, 1), (5, 1)]);
+        }
+    }
+}
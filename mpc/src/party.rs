@@ -1,262 +1,624 @@
 use crate::{
-    circuit, gate, field, share_receiver, share_sender, message, polynomial
+    circuit, gate, field, share_receiver, share_sender, message, multiplication, polynomial, preprocessing, rng, vss, Error
 };
 
-use log::{info, debug};
+use log::{info, debug, warn};
 
-use std::collections::{HashSet, HashMap};
+use std::collections::{BTreeSet, HashSet};
+use std::rc::Rc;
 
-pub struct Party<DataType: Clone> {
+pub struct Party<F: field::FiniteField> {
     id: usize,
-    secret: Vec<DataType>,
-    rx: Box<dyn share_receiver::ShareReceiver<message::Message<DataType>>>,
-    txs: Vec<Box<dyn share_sender::ShareSender<message::Message<DataType>>>>,
-    shares: Vec<HashMap<usize, DataType>>,
-    r_share: HashMap<usize, (DataType, DataType)>,
-    field: field::Field<DataType>,
-    circuit: circuit::Circuit<DataType>,
+    secret: Vec<F::Element>,
+    rx: Box<dyn share_receiver::ShareReceiver<message::Message<F::Element>>>,
+    txs: Vec<Box<dyn share_sender::ShareSender<message::Message<F::Element>>>>,
+    pool: preprocessing::Pool<F::Element>,
+    multiplication: Rc<dyn multiplication::Multiplication<F>>,
+    vss: Option<Rc<dyn vss::Commitments<F>>>,
+    field: F,
+    circuit: circuit::Circuit<F::Element>,
     threshold: usize,
-    past_messages: HashSet<message::Message<DataType>>,
+    past_messages: HashSet<message::Message<F::Element>>,
+    // parties whose shares `open` had to correct
+    misbehaving: BTreeSet<usize>,
+    // NOTE: messages are identified in the cache (self.past_messages) by round id. Every party
+    // runs the same sequence of rounds, sending one message to every other party in each.
+    round: usize,
+    rng: Box<dyn rng::CryptoRngCore>,
 }
 
-impl<DataType> Party<DataType>
-where DataType: field::FieldElement +
-                From<u16> +
-                std::fmt::Debug +
-                std::fmt::Display {
+impl<F: field::FiniteField> Party<F> {
 
     pub fn new(id: usize,
-                secret: Vec<DataType>,
-                rx: Box<dyn share_receiver::ShareReceiver<message::Message<DataType>>>, 
-                txs: Vec<Box<dyn share_sender::ShareSender<message::Message<DataType>>>>,
-                field: field::Field<DataType>,
-                circuit: circuit::Circuit<DataType>,
-                threshold: usize) -> Self {
-        let n_parties = circuit.get_n_parties() as usize;
-        Party {
+                secret: Vec<F::Element>,
+                rx: Box<dyn share_receiver::ShareReceiver<message::Message<F::Element>>>, 
+                txs: Vec<Box<dyn share_sender::ShareSender<message::Message<F::Element>>>>,
+                field: F,
+                circuit: circuit::Circuit<F::Element>,
+                threshold: usize) -> Result<Self, Error> {
+        circuit.validate()?;
+
+        Ok(Party {
             id, secret, rx, txs, field, circuit, threshold,
-            shares: vec![HashMap::new(); n_parties],
-            r_share: HashMap::new(),
+            pool: preprocessing::Pool::new(),
+            multiplication: Rc::new(multiplication::DegreeReduction),
+            vss: None,
             past_messages: HashSet::new(),
-        }
+            misbehaving: BTreeSet::new(),
+            round: 0,
+            rng: Box::new(rand::thread_rng()),
+        })
+    }
+
+    /// Replaces the default `rand::thread_rng()` used for sharing polynomials and masks,
+    /// e.g. with `rng::party_rng` to make a run reproducible.
+    pub fn with_rng(mut self, rng: impl rng::CryptoRngCore + 'static) -> Self {
+        self.rng = Box::new(rng);
+        self
     }
     
-    pub fn setup(mut self) -> Self {
+    /// Adds randomness from an earlier `preprocess`, which `run` consumes before any
+    /// generated by `setup`.
+    pub fn with_pool(mut self, mut pool: preprocessing::Pool<F::Element>) -> Self {
+        pool.extend(std::mem::take(&mut self.pool));
+        self.pool = pool;
+        self
+    }
+
+    /// Replaces the default `multiplication::DegreeReduction` used for the `Mul` gates. Every
+    /// party must use the same one.
+    pub fn with_multiplication(mut self, multiplication: impl multiplication::Multiplication<F> + 'static) -> Self {
+        self.multiplication = Rc::new(multiplication);
+        self
+    }
+
+    /// Makes the dealers of inputs commit to their sharing polynomials with `commitments`, so
+    /// that invalid sharings are caught and their dealers disqualified, their inputs counting
+    /// as zero. Adds a round of complaints and, if there are any, a round answering them.
+    /// Every party must use the same commitments.
+    pub fn with_vss(mut self, commitments: impl vss::Commitments<F> + 'static) -> Self {
+        self.vss = Some(Rc::new(commitments));
+        self
+    }
+
+    /// Generates the double sharings and triples `run` needs beyond those already in the pool.
+    pub fn setup(mut self) -> Result<Self, Error> {
         info!("Setupping party {}", self.id);
 
-        let n_gates = self.circuit.size();
-        
-        for gate_id in self.circuit.traverse() {
-            if matches!(self.circuit.get_gate(gate_id), gate::Gate::Mul { first: _, second: _, output: _ }) {
-                debug!("Party {} preparing r_shares for gate({})", self.id, gate_id);
-
-                let r = self.field.random();
-                debug!("Party{}: gate({}) r = {}", self.id, gate_id, r);
-                let s_poly = polynomial::Polynomial::random(r.clone(), self.threshold, self.field.clone());
-                let t_poly = polynomial::Polynomial::random(r, self.threshold * 2, self.field.clone());
-
-                // NOTE: messages are identified in the cache (self.past_messages) by
-                // gate_id (essentially "round_id");
-                // in order to avoid messing up the cache, s_share and t_share are sent with
-                // (gate_id + n_gates) and (gate_id + 2 * n_gates) respectively - unique "round_id's"
-                let s_shares = self.broadcast_poly(s_poly, gate_id + n_gates);
-                let t_shares = self.broadcast_poly(t_poly, gate_id + 2 * n_gates);
-                
-                self.r_share.insert(gate_id, (
-                    s_shares.into_iter().fold(DataType::from(0), |a, b| self.field.add(a, b)),
-                    t_shares.into_iter().fold(DataType::from(0), |a, b| self.field.add(a, b))
-                ));
-
-                debug!("Party{}: gate({}) r_share = {:?}", self.id, gate_id, self.r_share[&gate_id]);
+        let (double_sharings, triples) = self.multiplication.randomness(self.count_muls());
+        let pool = self.preprocess(
+            double_sharings.saturating_sub(self.pool.get_n_double_sharings()),
+            triples.saturating_sub(self.pool.get_n_triples())
+        )?;
+        self.pool.extend(pool);
+
+        info!("Party {} setup finished", self.id);
+
+        Ok(self)
+    }
+
+    /// Runs the offline phase with the other parties, which must all call it with the same
+    /// counts, and returns this party's shares of `double_sharings` double sharings and
+    /// `triples` Beaver triples. It takes at most two rounds and does not depend on the
+    /// secrets, so it can run long before them.
+    pub fn preprocess(&mut self, double_sharings: usize, triples: usize) -> Result<preprocessing::Pool<F::Element>, Error> {
+        let n_parties = self.circuit.get_n_parties() as usize;
+        let mut pool = preprocessing::Pool::new();
+        if double_sharings + triples == 0 {
+            return Ok(pool);
+        }
+
+        // every party deals a sharing of a random r with degree t (s) and 2t (t) per double
+        // sharing, and of random a and b with a double sharing for computing a * b per triple;
+        // each shared value is the sum of those dealt by every party
+        let dealt = 2 * double_sharings + 4 * triples;
+        let mut degrees = Vec::with_capacity(dealt / 2);
+        degrees.resize(double_sharings, vec![1, 2]);
+        degrees.resize(double_sharings + triples, vec![1, 1, 1, 2]);
+
+        let mut outgoing = vec![Vec::with_capacity(dealt); n_parties];
+        for group in degrees {
+            // the last two polynomials of a group are a double sharing and share r
+            let r = self.field.random(&mut *self.rng);
+            for (k, &degree) in group.iter().enumerate() {
+                let secret = if k + 2 >= group.len() { r.clone() } else { self.field.random(&mut *self.rng) };
+                let poly = polynomial::Polynomial::random(secret, self.threshold * degree, self.field.clone(), &mut *self.rng);
+                for (party, shares) in outgoing.iter_mut().enumerate() {
+                    shares.push(poly.eval(self.field.element(party as u16 + 1)));
+                }
             }
         }
+        let incoming = self.exchange(outgoing, &vec![dealt; n_parties])?;
+        let mut sums = (0..dealt).map(|k| incoming.iter().fold(self.field.zero(), |acc, shares| self.field.add(acc, shares[k].clone())));
 
-        info!("Party {} setup finished", self.id);
+        for _ in 0..double_sharings {
+            pool.push_double_sharing(sums.next().unwrap(), sums.next().unwrap());
+        }
 
-        self
+        let mut factors = Vec::with_capacity(triples);
+        let mut masks = preprocessing::Pool::new();
+        for _ in 0..triples {
+            factors.push((sums.next().unwrap(), sums.next().unwrap()));
+            masks.push_double_sharing(sums.next().unwrap(), sums.next().unwrap());
+        }
+        if triples > 0 {
+            let mut ctx = multiplication::Context::new(self, &mut masks);
+            let products = multiplication::Multiplication::multiply(&multiplication::DegreeReduction, &mut ctx, factors.clone())?;
+            for ((a, b), c) in factors.into_iter().zip(products) {
+                pool.push_triple(a, b, c);
+            }
+        }
+
+        debug!("Party{}: preprocessed {} double sharings and {} triples", self.id, double_sharings, triples);
+
+        Ok(pool)
+    }
+
+    pub(crate) fn get_field(&self) -> &F {
+        &self.field
     }
 
-    pub fn run(mut self) -> Vec<DataType> {
+    pub(crate) fn get_threshold(&self) -> usize {
+        self.threshold
+    }
+
+    fn count_muls(&self) -> usize {
+        self.circuit.traverse()
+            .filter(|&gate_id| matches!(self.circuit.get_gate(gate_id), gate::Gate::Mul { first: _, second: _, output: _ }))
+            .count()
+    }
+
+    pub fn run(self) -> Result<Vec<F::Element>, Error> {
+        self.run_with_report().map(|(results, _)| results)
+    }
+
+    /// Same as `run`, but also returns the ids of the parties caught sending wrong shares,
+    /// which were corrected. Misbehaving parties which were not caught are not among them.
+    pub fn run_with_report(mut self) -> Result<(Vec<F::Element>, Vec<usize>), Error> {
         info!("Running party {} with secret {:?}", self.id, self.secret);
 
-        let mut n_gates = 0;
+        // fails before any round if the pool is too small
+        let (double_sharings, triples) = self.multiplication.randomness(self.count_muls());
+        let mut randomness = self.pool.split_off(double_sharings, triples)?;
+
         let mut circuit = self.circuit.clone();
+        self.process_inputs(&mut circuit)?;
+
+        let depths = circuit.depths();
+        let mut layers = vec![Vec::new(); depths.iter().max().map_or(1, |depth| depth + 1)];
         for gate_id in circuit.traverse() {
-            debug!("Party{}: processing gate {}", self.id, gate_id);
+            layers[depths[gate_id]].push(gate_id);
+        }
 
-            let output = match circuit.get_gate(gate_id) {
-                gate::Gate::Input { ref party, ref circuit_id, output: _ } => {
-                    self.process_input(gate_id, *party, *circuit_id)
-                }
-                gate::Gate::Add { ref first, ref second, output: _ } => {
-                    self.process_add(gate_id, circuit.get_gate(*first), circuit.get_gate(*second))
-                }
-                gate::Gate::MulByConst { ref first, ref second, output: _ } => {
-                    self.process_mul_by_const(gate_id, circuit.get_gate(*first), second.clone())
-                }
-                gate::Gate::Mul { ref first, ref second, output: _ } => {
-                    self.process_mul(gate_id, circuit.get_gate(*first), circuit.get_gate(*second))
+        for layer in layers {
+            // the Mul gates of a layer only read lower layers, so they are multiplied together
+            let (muls, local): (Vec<usize>, Vec<usize>) = layer.into_iter()
+                .partition(|&gate_id| matches!(circuit.get_gate(gate_id), gate::Gate::Mul { first: _, second: _, output: _ }));
+            if !muls.is_empty() {
+                let outputs = self.process_muls(&muls, &mut randomness, &circuit)?;
+                for (gate_id, output) in muls.into_iter().zip(outputs) {
+                    circuit.get_gate_mut(gate_id).set_output(output);
                 }
-            };
+            }
 
-            circuit.get_gate_mut(gate_id).set_output(output);
+            for gate_id in local {
+                debug!("Party{}: processing gate {}", self.id, gate_id);
 
-            n_gates += 1;
+                let output = match circuit.get_gate(gate_id) {
+                    gate::Gate::Add { ref first, ref second, output: _ } => {
+                        self.process_add(gate_id, circuit.get_gate(*first), circuit.get_gate(*second))
+                    }
+                    gate::Gate::MulByConst { ref first, ref second, output: _ } => {
+                        self.process_mul_by_const(gate_id, circuit.get_gate(*first), second.clone())
+                    }
+                    gate::Gate::AddConst { ref first, ref second, output: _ } => {
+                        self.process_add_const(gate_id, circuit.get_gate(*first), second.clone())
+                    }
+                    gate::Gate::Sub { ref first, ref second, output: _ } => {
+                        self.process_sub(gate_id, circuit.get_gate(*first), circuit.get_gate(*second))
+                    }
+                    gate::Gate::Neg { ref first, output: _ } => {
+                        self.process_neg(gate_id, circuit.get_gate(*first))
+                    }
+                    // NOTE: the constant polynomial is a valid sharing of itself, so no round is needed
+                    gate::Gate::Const { ref value, output: _ } => value.clone(),
+                    // inputs were shared by process_inputs
+                    gate::Gate::Input { party: _, circuit_id: _, output: _ } => continue,
+                    gate::Gate::Mul { first: _, second: _, output: _ } => unreachable!(),
+                };
+
+                circuit.get_gate_mut(gate_id).set_output(output);
+            }
         }
 
-        let results = circuit.get_roots().into_iter().map(
-            |gate_id| self.process_output(n_gates, circuit.get_gate(gate_id).get_output())
-        ).collect();
+        let results = self.process_outputs(
+            circuit.get_roots().into_iter().map(|gate_id| circuit.get_gate(gate_id).get_output()).collect()
+        )?;
 
         info!("Party {} finished with output {:?}", self.id, results);
 
-        results
+        Ok((results, self.misbehaving.into_iter().collect()))
     }
 
-    fn safe_recv(&mut self, gate_id: usize) -> message::Message<DataType> {
-        let msg = match self.past_messages.iter().find(|&m| m.get_to() == self.id && m.get_gate() == gate_id) {
+    fn safe_recv(&mut self, round: usize) -> Result<message::Message<F::Element>, Error> {
+        let msg = match self.past_messages.iter().find(|&m| m.get_round() == round) {
             Some(msg) => msg.clone(),
             None => loop {
-                        let msg = self.rx.recv();
-                        if msg.get_to() == self.id && msg.get_gate() == gate_id {
+                        let msg = self.rx.recv()?;
+                        self.check_sender(&msg)?;
+                        if msg.get_round() == round {
                             break msg;
-                        } else {
-                            self.past_messages.insert(msg.clone());
+                        } else if msg.get_round() < round {
+                            // a round which went ahead without this message
+                            debug!("Party{}: round({}) dropping late message from Party{} for round({})",
+                                    self.id, round, msg.get_from(), msg.get_round());
+                        } else if !self.past_messages.insert(msg.clone()) {
+                            return Err(self.unexpected(&msg));
                         }
-                    }            
+                    }
         };
         self.past_messages.remove(&msg);
-        
-        msg
+
+        Ok(msg)
     }
 
-    fn process_input(&mut self, gate_id: usize, party: usize, circuit_id: usize) -> DataType {
-        debug!("Party{}: process_input({}, {})", self.id, gate_id, party);
-
-        if self.id == party {
-            let poly = polynomial::Polynomial::random(self.secret[circuit_id].clone(), self.threshold, self.field.clone());
-            (0..self.circuit.get_n_parties())
-                .map(|i| (i, poly.eval(DataType::from(i + 1))))
-                .for_each(|(party, share)| {
-                    let party = party as usize;
-                    if party == self.id {
-                        self.shares[party].insert(gate_id, share);
-                    } else {
-                        self.txs[party].send(message::Message::new(self.id, party, gate_id, share));
-                    }
-                });
+    fn check_sender(&self, msg: &message::Message<F::Element>) -> Result<(), Error> {
+        let n_parties = self.circuit.get_n_parties() as usize;
+        if msg.get_to() != self.id || msg.get_from() == self.id || msg.get_from() >= n_parties {
+            Err(self.unexpected(msg))
         } else {
-            let msg = self.safe_recv(gate_id);
-            self.shares[party].insert(gate_id, msg.get_share());
+            Ok(())
+        }
+    }
+
+    fn unexpected(&self, msg: &message::Message<F::Element>) -> Error {
+        Error::UnexpectedSender { from: msg.get_from(), to: msg.get_to(), round: msg.get_round() }
+    }
+
+    // Shares the secrets of every input gate in a single round, followed by the rounds of
+    // `verify_inputs` with VSS.
+    fn process_inputs(&mut self, circuit: &mut circuit::Circuit<F::Element>) -> Result<(), Error> {
+        let n_parties = self.circuit.get_n_parties() as usize;
+        let inputs: Vec<(usize, usize, usize)> = circuit.traverse()
+            .filter_map(|gate_id| match circuit.get_gate(gate_id) {
+                gate::Gate::Input { party, circuit_id, output: _ } => Some((gate_id, *party, *circuit_id)),
+                _ => None,
+            })
+            .collect();
+        if inputs.is_empty() {
+            return Ok(());
         }
 
-        debug!("Party{}: shares[{}][{}] = {}", self.id, party, gate_id, self.shares[party][&gate_id]);
+        // with VSS every share is followed by the share of its blinding polynomial, and the
+        // dealer's message ends with the commitments to the polynomials of all its inputs
+        let vss = self.vss.clone();
+        let (share_width, commitments_width) = match &vss {
+            Some(vss) => (2, (self.threshold + 1) * vss.width()),
+            None => (1, 0),
+        };
+
+        let mut outgoing = vec![Vec::new(); n_parties];
+        let mut commitments = Vec::new();
+        let mut counts = vec![0; n_parties];
+        for &(gate_id, party, circuit_id) in &inputs {
+            debug!("Party{}: process_input({}, {})", self.id, gate_id, party);
+
+            counts[party] += 1;
+            if party == self.id {
+                let poly = polynomial::Polynomial::random(self.secret[circuit_id].clone(), self.threshold, self.field.clone(), &mut *self.rng);
+                let blinding = match &vss {
+                    Some(vss) => {
+                        let blinding = polynomial::Polynomial::random(self.field.random(&mut *self.rng), self.threshold, self.field.clone(), &mut *self.rng);
+                        commitments.extend(vss.commit(poly.get_coeffs(), blinding.get_coeffs()));
+                        Some(blinding)
+                    }
+                    None => None,
+                };
+                for (i, shares) in outgoing.iter_mut().enumerate() {
+                    let x = self.field.element(i as u16 + 1);
+                    shares.push(poly.eval(x.clone()));
+                    if let Some(blinding) = &blinding {
+                        shares.push(blinding.eval(x));
+                    }
+                }
+            }
+        }
+        // the shares dealt to each party, which verify_inputs may have to reveal
+        let dealt = outgoing.clone();
+        for shares in outgoing.iter_mut() {
+            shares.extend(commitments.iter().cloned());
+        }
+
+        let lengths: Vec<usize> = counts.iter().map(|count| count * (share_width + commitments_width)).collect();
+        let (mut received, commitments): (Vec<_>, Vec<_>) = self.exchange(outgoing, &lengths)?
+            .into_iter()
+            .zip(&counts)
+            .map(|(mut shares, count)| {
+                let commitments = shares.split_off(count * share_width);
+                (shares, commitments)
+            })
+            .unzip();
+
+        let disqualified = match vss {
+            Some(vss) => self.verify_inputs(&*vss, &mut received, &commitments, &dealt)?,
+            None => Vec::new(),
+        };
+
+        let mut received: Vec<_> = received.into_iter().map(|shares| shares.into_iter().step_by(share_width)).collect();
+        for (gate_id, party, _) in inputs {
+            let share = received[party].next().unwrap();
+            // NOTE: the constant zero is a valid sharing of itself
+            let share = if disqualified.contains(&party) { self.field.zero() } else { share };
+            debug!("Party{}: gate({}) input share of Party{} = {}", self.id, gate_id, party, share);
+            circuit.get_gate_mut(gate_id).set_output(share);
+        }
+
+        Ok(())
+    }
+
+    // Checks the shares received[dealer], each followed by its blinding share, against
+    // commitments[dealer], and complains in a round about the dealers of the failing ones,
+    // echoing the digest of the commitments of every dealer. The digest echoed by n - t
+    // parties is the dealer's, and the parties echoing another one complain too; a dealer
+    // without one is disqualified. Each accused dealer then sends, in another round, its
+    // commitments again with the shares of the parties complaining about it, which replace
+    // theirs if valid and disqualify it otherwise. Returns the disqualified dealers.
+    fn verify_inputs(&mut self,
+                     vss: &dyn vss::Commitments<F>,
+                     received: &mut [Vec<F::Element>],
+                     commitments: &[Vec<F::Element>],
+                     dealt: &[Vec<F::Element>]) -> Result<Vec<usize>, Error> {
+        let n_parties = self.circuit.get_n_parties() as usize;
+        let field = self.field.clone();
+        let width = (self.threshold + 1) * vss.width();
+        let valid = |shares: &[F::Element], commitments: &[F::Element], party: usize| {
+            let x = field.element(party as u16 + 1);
+            shares.chunks(2).zip(commitments.chunks(width))
+                .all(|(shares, commitments)| vss.verify(commitments, &x, &shares[0], &shares[1]))
+        };
+
+        let echo_width = 1 + vss.digest_width();
+        let echoes: Vec<F::Element> = (0..n_parties)
+            .flat_map(|dealer| {
+                let complaint = if dealer == self.id || valid(&received[dealer], &commitments[dealer], self.id) {
+                    self.field.zero()
+                } else {
+                    self.field.one()
+                };
+                std::iter::once(complaint).chain(vss.digest(&commitments[dealer]))
+            })
+            .collect();
+        let incoming = self.exchange(vec![echoes; n_parties], &vec![n_parties * echo_width; n_parties])?;
+
+        // NOTE: with t < n / 2, at most one digest can be echoed by n - t parties
+        let mut disqualified = Vec::new();
+        let mut digests = vec![Vec::new(); n_parties];
+        // complainers[dealer] are the parties complaining about dealer, in id order
+        let mut complainers = vec![Vec::new(); n_parties];
+        for dealer in 0..n_parties {
+            let echoes: Vec<&[F::Element]> = incoming.iter()
+                .map(|echoes| &echoes[dealer * echo_width..(dealer + 1) * echo_width])
+                .collect();
+            let digest = echoes.iter()
+                .map(|echo| &echo[1..])
+                .find(|&digest| echoes.iter().filter(|echo| &echo[1..] == digest).count() >= n_parties - self.threshold);
+            match digest {
+                Some(digest) => {
+                    complainers[dealer] = (0..n_parties)
+                        .filter(|&party| party != dealer && (echoes[party][0] != self.field.zero() || &echoes[party][1..] != digest))
+                        .collect();
+                    digests[dealer] = digest.to_vec();
+                }
+                None => disqualified.push(dealer),
+            }
+        }
+        if complainers.iter().any(|complainers| !complainers.is_empty()) {
+            info!("Party{}: complaints about dealers {:?}", self.id, complainers);
+
+            let accused = |dealer: usize| !complainers[dealer].is_empty() && !disqualified.contains(&dealer);
+            let answers: Vec<F::Element> = if accused(self.id) {
+                commitments[self.id].iter().cloned()
+                    .chain(complainers[self.id].iter().flat_map(|&party| dealt[party].iter().cloned()))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let lengths: Vec<usize> = (0..n_parties)
+                .map(|dealer| if accused(dealer) { commitments[dealer].len() + complainers[dealer].len() * received[dealer].len() } else { 0 })
+                .collect();
+            let incoming = self.exchange(vec![answers; n_parties], &lengths)?;
+
+            for (dealer, answers) in incoming.into_iter().enumerate() {
+                if lengths[dealer] == 0 {
+                    continue;
+                }
+                let (answered_commitments, answers) = answers.split_at(commitments[dealer].len());
+                if vss.digest(answered_commitments) != digests[dealer] {
+                    disqualified.push(dealer);
+                    continue;
+                }
+                for (answers, &party) in answers.chunks(received[dealer].len().max(1)).zip(&complainers[dealer]) {
+                    if !valid(answers, answered_commitments, party) {
+                        disqualified.push(dealer);
+                        break;
+                    } else if party == self.id {
+                        received[dealer].clone_from_slice(answers);
+                    }
+                }
+            }
+        }
+        if !disqualified.is_empty() {
+            warn!("Party{}: disqualified dealers {:?}", self.id, disqualified);
+        }
 
-        self.shares[party][&gate_id].clone()
+        Ok(disqualified)
     }
 
-    fn process_add(&self, _gate_id: usize, first: &gate::Gate<DataType>, second: &gate::Gate<DataType>) -> DataType {
+    fn process_add(&self, _gate_id: usize, first: &gate::Gate<F::Element>, second: &gate::Gate<F::Element>) -> F::Element {
         debug!("Party{}: process_add({}, {})", self.id, first.get_output(), second.get_output());
         self.field.add(first.get_output(), second.get_output())
     }
 
-    fn process_mul_by_const(&mut self, _gate_id: usize, first: &gate::Gate<DataType>, second: DataType) -> DataType {
+    fn process_mul_by_const(&mut self, _gate_id: usize, first: &gate::Gate<F::Element>, second: F::Element) -> F::Element {
         debug!("Party{}: process_mul_by_const({}, {})", self.id, first.get_output(), second);
         self.field.mul(first.get_output(), second)
     }
 
-    fn process_mul(&mut self, gate_id: usize, first: &gate::Gate<DataType>, second: &gate::Gate<DataType>) -> DataType {
-        let c_share = self.field.mul(first.get_output(), second.get_output());
+    // NOTE: f(x) + c is a sharing of the secret plus c with the same degree
+    fn process_add_const(&self, _gate_id: usize, first: &gate::Gate<F::Element>, second: F::Element) -> F::Element {
+        debug!("Party{}: process_add_const({}, {})", self.id, first.get_output(), second);
+        self.field.add(first.get_output(), second)
+    }
 
-        debug!("Party{}: process_mul({}, {}, {}) c_share = {}",
-            self.id, gate_id, first.get_output(), second.get_output(), c_share);
+    fn process_sub(&self, _gate_id: usize, first: &gate::Gate<F::Element>, second: &gate::Gate<F::Element>) -> F::Element {
+        debug!("Party{}: process_sub({}, {})", self.id, first.get_output(), second.get_output());
+        self.field.sub(first.get_output(), second.get_output())
+    }
+
+    fn process_neg(&self, _gate_id: usize, first: &gate::Gate<F::Element>) -> F::Element {
+        debug!("Party{}: process_neg({})", self.id, first.get_output());
+        self.field.sub(self.field.zero(), first.get_output())
+    }
 
-        let g_share = self.field.add(c_share, self.r_share[&gate_id].1.clone());
+    fn process_muls(&mut self, gate_ids: &[usize], randomness: &mut preprocessing::Pool<F::Element>, circuit: &circuit::Circuit<F::Element>) -> Result<Vec<F::Element>, Error> {
+        let pairs = gate_ids.iter().map(|&gate_id| {
+            let (first, second) = match circuit.get_gate(gate_id) {
+                gate::Gate::Mul { first, second, output: _ } => (circuit.get_gate(*first), circuit.get_gate(*second)),
+                _ => unreachable!(),
+            };
+            debug!("Party{}: process_mul({}, {}, {})", self.id, gate_id, first.get_output(), second.get_output());
 
-        debug!("Party{}: process_mul({}, {}, {}) g_share = {}",
-            self.id, gate_id, first.get_output(), second.get_output(), g_share);
-        
-        let shares = self.broadcast_share(g_share, gate_id);
+            (first.get_output(), second.get_output())
+        }).collect();
 
-        let g = polynomial::Polynomial::interpolate(shares, &self.field,
-            self.circuit.get_n_parties() as usize, |x| DataType::from(x as u16));
+        let multiplication = self.multiplication.clone();
+        multiplication.multiply(&mut multiplication::Context::new(self, randomness), pairs)
+    }
 
-        let result = self.field.sub(g, self.r_share[&gate_id].0.clone());
+    fn process_outputs(&mut self, outputs: Vec<F::Element>) -> Result<Vec<F::Element>, Error> {
+        debug!("Party{}: process_outputs({:?})", self.id, outputs);
 
-        result
+        self.open(outputs, self.threshold)
     }
-    
-    fn process_output(&mut self, n_gates: usize, output: DataType) -> DataType {
+
+    // Sends shares of degree `degree` to every other party in the next round and decodes each
+    // value from the shares received so far, which is only accepted once degree + t + 1 of
+    // them agree with the decoded polynomial: at most t are wrong, so degree + 1 honest shares
+    // lie on it and it is the one they were dealt on. Until then the shares of one more party
+    // are awaited, up to all n of them, which must all agree if n < degree + t + 1. The senders
+    // of the corrected shares are recorded as misbehaving.
+    pub(crate) fn open(&mut self, shares: Vec<F::Element>, degree: usize) -> Result<Vec<F::Element>, Error> {
         let n_parties = self.circuit.get_n_parties() as usize;
+        let agreeing = (degree + self.threshold + 1).min(n_parties);
+        let lengths = vec![shares.len(); n_parties];
+
+        let round = self.round;
+        let mut incoming = self.send_round(vec![shares; n_parties], agreeing == n_parties)?;
+        let mut received = agreeing;
+        self.receive_round(round, &mut incoming, &lengths, received)?;
+        let decoded = loop {
+            match self.decode(&incoming, lengths[self.id], degree) {
+                Some(decoded) if decoded.iter().all(|(_, bad)| received - bad.len() >= agreeing) => break decoded,
+                _ if received < n_parties => {
+                    debug!("Party{}: round({}) cannot decode from {} parties with {} agreeing, waiting for one more",
+                            self.id, round, received, agreeing);
+                    received += 1;
+                    self.receive_round(round, &mut incoming, &lengths, received)?;
+                }
+                _ => {
+                    self.end_round(round);
+                    return Err(Error::InconsistentShares { round, misbehaving: self.misbehaving.iter().copied().collect() });
+                }
+            }
+        };
+        self.end_round(round);
 
-        debug!("Party{}: process_output({}, {})", self.id, n_gates, output);
+        let mut values = Vec::with_capacity(decoded.len());
+        for (value, bad) in decoded {
+            if !bad.is_empty() {
+                warn!("Party{}: round({}) wrong shares from parties {:?}", self.id, round, bad);
+                self.misbehaving.extend(bad);
+            }
+            values.push(value);
+        }
+        Ok(values)
+    }
 
-        self.broadcast_share(output.clone(), n_gates)
-            .into_iter()
-            .enumerate()
-            .for_each(|(party, share)| {
-                self.shares[party].insert(n_gates, share);
-            });
+    // Decodes each of the `n_values` values opened with `incoming`, along with the parties
+    // whose shares of it were wrong, or returns `None` if one of them cannot be decoded.
+    fn decode(&self, incoming: &[Option<Vec<F::Element>>], n_values: usize, degree: usize) -> Option<Vec<(F::Element, Vec<usize>)>> {
+        (0..n_values).map(|k| {
+            let shares: Vec<(usize, F::Element)> = incoming.iter().enumerate()
+                .filter_map(|(party, shares)| shares.as_ref().map(|shares| (party, shares[k].clone())))
+                .collect();
+            debug!("Party{}: decoding {:?}", self.id, shares);
+            polynomial::Polynomial::decode(shares, degree, &self.field, |x| self.field.element(x as u16))
+        }).collect()
+    }
 
-        debug!("Party{}: interpolating {:?}", self.id, (0..n_parties).map(|p| self.shares[p][&n_gates].clone()).collect::<Vec<_>>());
+    // Runs the next round: sends outgoing[party] to every other party and collects the shares
+    // each of them sent, which must be lengths[party] shares from party.
+    fn exchange(&mut self, outgoing: Vec<Vec<F::Element>>, lengths: &[usize]) -> Result<Vec<Vec<F::Element>>, Error> {
+        let n_parties = self.circuit.get_n_parties() as usize;
+        let round = self.round;
+        let mut incoming = self.send_round(outgoing, true)?;
+        self.receive_round(round, &mut incoming, lengths, n_parties)?;
+        self.end_round(round);
 
-        polynomial::Polynomial::interpolate(
-            (0..n_parties).map(|party| self.shares[party][&n_gates].clone()).collect(),
-            &self.field, n_parties, |x| DataType::from(x as u16)
-        )
+        Ok(incoming.into_iter().map(Option::unwrap).collect())
     }
 
-    fn broadcast_poly(&mut self, poly: polynomial::Polynomial<DataType>, gate_id: usize) -> Vec<DataType> {
-        let n_parties = self.circuit.get_n_parties();
-        let mut shares = vec![DataType::from(0); n_parties as usize];
-
-        (0..n_parties)
-            .map(|i| (i, poly.eval(DataType::from(i + 1))))
-            .for_each(|(party, share)| {
-                let party = party as usize;
-                if party == self.id {
-                    shares[party] = share;
-                } else {
-                    debug!("Party{}: gate({}) send share {} to Party{}",
-                            self.id, gate_id, share, party);
-                    self.txs[party].send(message::Message::new(self.id, party, gate_id, share));
+    // Starts the next round, `self.round`, by sending outgoing[party] to every other party.
+    // Returns the shares received in it so far, only those of this party.
+    //
+    // A party which cannot be reached fails the round if `wait_for_all`; otherwise it is gone,
+    // possibly done after a quorum, and only missed by the rounds waiting for it.
+    fn send_round(&mut self, outgoing: Vec<Vec<F::Element>>, wait_for_all: bool) -> Result<Vec<Option<Vec<F::Element>>>, Error> {
+        let n_parties = self.circuit.get_n_parties() as usize;
+        let round = self.round;
+        self.round += 1;
+        let mut incoming = vec![None; n_parties];
+
+        for (party, shares) in outgoing.into_iter().enumerate() {
+            if party == self.id {
+                incoming[party] = Some(shares);
+            } else {
+                debug!("Party{}: round({}) send shares {:?} to Party{}",
+                        self.id, round, shares, party);
+                match self.txs[party].send(message::Message::new(self.id, party, round, shares)) {
+                    Err(e) if !wait_for_all => warn!("Party{}: round({}) cannot send to Party{}: {}", self.id, round, party, e),
+                    result => result?,
                 }
-            });
-        (1..n_parties)
-            .for_each(|_| {
-                let msg = self.safe_recv(gate_id);
-                debug!("Party{}: gate({}) recv share {} from Party{}",
-                            self.id, gate_id, msg.get_share(), msg.get_from());
-                shares[msg.get_from()] = msg.get_share();
-            });
-
-        shares
+            }
+        }
+
+        Ok(incoming)
     }
 
-    fn broadcast_share(&mut self, share: DataType, gate_id: usize) -> Vec<DataType> {
-        let n_parties = self.circuit.get_n_parties();
-        let mut shares = vec![DataType::from(0); n_parties as usize];
+    // Receives shares of `round` into `incoming` until `count` parties, this one included,
+    // have sent theirs.
+    fn receive_round(&mut self, round: usize, incoming: &mut [Option<Vec<F::Element>>], lengths: &[usize], count: usize) -> Result<(), Error> {
+        for _ in incoming.iter().filter(|shares| shares.is_some()).count()..count {
+            let msg = self.safe_recv(round)?;
+            debug!("Party{}: round({}) recv shares {:?} from Party{}",
+                        self.id, round, msg.get_shares(), msg.get_from());
+            if incoming[msg.get_from()].is_some() {
+                return Err(self.unexpected(&msg));
+            }
+            let shares = msg.get_shares();
+            if shares.len() != lengths[msg.get_from()] {
+                return Err(Error::WrongShareCount {
+                    from: msg.get_from(), round, expected: lengths[msg.get_from()], actual: shares.len()
+                });
+            }
+            incoming[msg.get_from()] = Some(shares);
+        }
 
-        (0..n_parties)
-            .map(|i| (i, share.clone()))
-            .for_each(|(party, share)| {
-                let party = party as usize;
-                if party == self.id {
-                    shares[party] = share;
-                } else {
-                    debug!("Party{}: gate({}) send share {} to Party{}",
-                            self.id, gate_id, share, party);
-                    self.txs[party].send(message::Message::new(self.id, party, gate_id, share));
-                }
-            });
-        (1..n_parties)
-            .for_each(|_| {
-                let msg = self.safe_recv(gate_id);
-                debug!("Party{}: gate({}) recv share {} from Party{}",
-                            self.id, gate_id, msg.get_share(), msg.get_from());
-                shares[msg.get_from()] = msg.get_share();
-            });
-
-        shares
+        Ok(())
+    }
+
+    // Forgets the messages of `round` which arrived early but were not needed.
+    fn end_round(&mut self, round: usize) {
+        self.past_messages.retain(|msg| msg.get_round() > round);
     }
 }
@@ -1,20 +1,25 @@
-use crate::field;
+use crate::field::FiniteField;
+use rand::{RngCore, CryptoRng};
 
-pub(crate) struct Polynomial<DataType> {
-    coeffs: Vec<DataType>,
-    field: field::Field<DataType>
+pub(crate) struct Polynomial<F: FiniteField> {
+    coeffs: Vec<F::Element>,
+    field: F
 }
 
-impl<DataType: field::FieldElement + Clone> Polynomial<DataType> {
+impl<F: FiniteField> Polynomial<F> {
 
-    pub(crate) fn random(c: DataType, degree: usize, mut field: field::Field<DataType>) -> Polynomial<DataType> {
+    pub(crate) fn random<R: RngCore + CryptoRng + ?Sized>(c: F::Element, degree: usize, field: F, rng: &mut R) -> Polynomial<F> {
         Polynomial {
-            coeffs: std::iter::once(c).chain((1..degree + 1).map(|_| field.random())).collect(),
+            coeffs: std::iter::once(c).chain((1..degree + 1).map(|_| field.random(rng))).collect(),
             field
         }
     }
 
-    pub(crate) fn eval(&self, x: DataType) -> DataType {
+    pub(crate) fn get_coeffs(&self) -> &[F::Element] {
+        &self.coeffs
+    }
+
+    pub(crate) fn eval(&self, x: F::Element) -> F::Element {
         let mut result = self.field.zero();
 
         for coeff in self.coeffs.iter().rev() {
@@ -25,7 +30,7 @@ impl<DataType: field::FieldElement + Clone> Polynomial<DataType> {
         result
     }
 
-    pub(crate) fn lagrange(knots: impl Iterator<Item = DataType>, i: DataType, field: &field::Field<DataType>) -> DataType {
+    pub(crate) fn lagrange(knots: impl Iterator<Item = F::Element>, i: F::Element, field: &F) -> F::Element {
         knots
             .map(|knot| {
                 if knot == i {
@@ -37,40 +42,148 @@ impl<DataType: field::FieldElement + Clone> Polynomial<DataType> {
             .fold(field.one(), |a, b| field.mul(a, b))
     }
 
-    pub(crate) fn interpolate<F>(shares: Vec<DataType>, 
-                                field: &field::Field<DataType>, 
-                                n_parties: usize, 
-                                convert: F) -> DataType 
-    where 
-        F: Fn(usize)->DataType
+    /// Value at zero of the polynomial through `shares`, pairs of a party and its value at
+    /// `convert(party + 1)`, using the Lagrange coefficients of exactly these parties.
+    pub(crate) fn interpolate<C>(shares: Vec<(usize, F::Element)>,
+                                field: &F,
+                                convert: C) -> F::Element
+    where
+        C: Fn(usize) -> F::Element
     {
-        (0..n_parties)
-                .map(|party| (shares[party].clone(),
+        shares.iter()
+                .map(|(party, share)| (share.clone(),
                     Polynomial::lagrange(
-                        (0..n_parties).map(|i| convert(i + 1)),
+                        shares.iter().map(|(i, _)| convert(i + 1)),
                         convert(party + 1),
-                        &field
+                        field
                     )))
                 .map(|(share, lagr)| field.mul(share, lagr))
                 .fold(field.zero(), |a, b| field.add(a, b))
     }
+
+    /// Berlekamp-Welch decoding of `shares`, pairs of a party and what should be the value
+    /// at `convert(party + 1)` of a polynomial of degree `degree`. Corrects up to
+    /// (n - degree - 1) / 2 wrong shares out of n, so t of them with n >= 3t + 1 and degree t.
+    ///
+    /// Returns the value at zero together with the parties whose shares were wrong, or
+    /// `None` if there are too many of them to correct.
+    pub(crate) fn decode<C>(shares: Vec<(usize, F::Element)>,
+                            degree: usize,
+                            field: &F,
+                            convert: C) -> Option<(F::Element, Vec<usize>)>
+    where
+        C: Fn(usize) -> F::Element
+    {
+        let n = shares.len();
+        if n <= degree {
+            return None;
+        }
+        if n == degree + 1 {
+            // any n shares lie on a polynomial of degree n - 1, so there is nothing to check
+            return Some((Polynomial::interpolate(shares, field, convert), vec![]));
+        }
+        let errors = (n - degree - 1) / 2;
+        let xs: Vec<F::Element> = shares.iter().map(|(party, _)| convert(party + 1)).collect();
+        let powers = |x: &F::Element, count: usize| -> Vec<F::Element> {
+            std::iter::successors(Some(field.one()), |p| Some(field.mul(p.clone(), x.clone()))).take(count).collect()
+        };
+
+        // the error locator E, monic of degree e, and Q = P * E of degree e + degree satisfy
+        // Q(x) = y * E(x) at every share; the unknowns are the coefficients of Q, then those
+        // of E below x^e
+        let rows = xs.iter().zip(shares.iter().map(|(_, y)| y))
+            .map(|(x, y)| {
+                let x_powers = powers(x, errors + degree + 2);
+                let mut row = x_powers[..errors + degree + 1].to_vec();
+                row.extend(x_powers[..errors].iter().map(|p| field.sub(field.zero(), field.mul(y.clone(), p.clone()))));
+                row.push(field.mul(y.clone(), x_powers[errors].clone()));
+                row
+            })
+            .collect();
+        let solution = solve(rows, field)?;
+        let (q, e) = solution.split_at(errors + degree + 1);
+
+        // divides Q by E from the top coefficient down
+        let mut remainder = q.to_vec();
+        let mut quotient = vec![field.zero(); degree + 1];
+        for k in (0..=degree).rev() {
+            let coeff = remainder[k + errors].clone();
+            for (j, e_j) in e.iter().enumerate() {
+                remainder[k + j] = field.sub(remainder[k + j].clone(), field.mul(coeff.clone(), e_j.clone()));
+            }
+            remainder[k + errors] = field.zero();
+            quotient[k] = coeff;
+        }
+        if remainder.iter().any(|r| *r != field.zero()) {
+            return None;
+        }
+
+        let poly = Polynomial { coeffs: quotient, field: field.clone() };
+        let bad: Vec<usize> = xs.into_iter().zip(shares)
+            .filter(|(x, (_, y))| poly.eval(x.clone()) != *y)
+            .map(|(_, (party, _))| party)
+            .collect();
+        if bad.len() > errors {
+            return None;
+        }
+
+        Some((poly.coeffs[0].clone(), bad))
+    }
+}
+
+// Solves the linear system whose rows are the coefficients followed by the constant, with
+// free unknowns set to zero, or returns `None` if it has no solution.
+fn solve<F: FiniteField>(mut rows: Vec<Vec<F::Element>>, field: &F) -> Option<Vec<F::Element>> {
+    let n_unknowns = rows.first().map_or(0, |row| row.len() - 1);
+    let mut pivots = Vec::new();
+
+    for col in 0..n_unknowns {
+        let rank = pivots.len();
+        let pivot = match (rank..rows.len()).find(|&row| rows[row][col] != field.zero()) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        rows.swap(rank, pivot);
+        let inv = field.inv(rows[rank][col].clone());
+        let pivot_row: Vec<F::Element> = rows[rank].iter().map(|a| field.mul(a.clone(), inv.clone())).collect();
+        for (k, row) in rows.iter_mut().enumerate() {
+            if k != rank && row[col] != field.zero() {
+                let factor = row[col].clone();
+                for (a, b) in row.iter_mut().zip(&pivot_row) {
+                    *a = field.sub(a.clone(), field.mul(factor.clone(), b.clone()));
+                }
+            }
+        }
+        rows[rank] = pivot_row;
+        pivots.push(col);
+    }
+
+    // a remaining row 0 = c with c != 0 makes the system inconsistent
+    if rows[pivots.len()..].iter().any(|row| row[n_unknowns] != field.zero()) {
+        return None;
+    }
+    let mut solution = vec![field.zero(); n_unknowns];
+    for (row, col) in pivots.into_iter().enumerate() {
+        solution[col] = rows[row][n_unknowns].clone();
+    }
+    Some(solution)
 }
 
 #[cfg(test)]
 mod tests {
     use super::Polynomial;
-    use super::field::Field;
+    use crate::field::{Field, Fp, PrimeField, BinaryField, FiniteField};
 
     #[test]
     fn test_random() {
-        let poly = Polynomial::random(2u8, 5, Field::new(13u8));
+        let poly = Polynomial::random(2u8, 5, Field::new(13u8), &mut rand::thread_rng());
         assert_eq!(poly.coeffs.len(), 6);
         assert_eq!(poly.coeffs[0], 2u8);
     }
 
     #[test]
     fn test_eval() {
-        let poly = Polynomial::random(2u8, 2, Field::new(13u8));
+        let poly = Polynomial::random(2u8, 2, Field::new(13u8), &mut rand::thread_rng());
         let c0 = 2u8;
         let c1 = poly.coeffs[1];
         let c2 = poly.coeffs[2];
@@ -89,10 +202,90 @@ mod tests {
     #[test]
     fn test_interpolate() {
         let field = Field::new(13u16);
-        let poly = Polynomial::random(2u16, 2, field.clone());
+        let poly = Polynomial::random(2u16, 2, field.clone(), &mut rand::thread_rng());
         assert_eq!(Polynomial::interpolate(
-            (1..4).map(|i| poly.eval(i)).collect(),
-            &field, 3, |x| x as u16
+            (0..3).map(|party| (party, poly.eval(party as u16 + 1))).collect(),
+            &field, |x| x as u16
         ), 2u16);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_interpolate_fp() {
+        let poly = Polynomial::random(Fp::<251>::new(42), 2, PrimeField::<251>, &mut rand::thread_rng());
+        assert_eq!(Polynomial::interpolate(
+            (0..3).map(|party| (party, poly.eval(Fp::new(party as u64 + 1)))).collect(),
+            &PrimeField::<251>, |x| Fp::new(x as u64)
+        ), Fp::new(42));
+    }
+
+    #[test]
+    fn test_random_seeded() {
+        let field = Field::new(251u16);
+        let first = Polynomial::random(2u16, 4, field.clone(), &mut crate::rng::party_rng(1, 0));
+        let second = Polynomial::random(2u16, 4, field, &mut crate::rng::party_rng(1, 0));
+        assert_eq!(first.coeffs, second.coeffs);
+    }
+
+    #[test]
+    fn test_interpolate_binary() {
+        let field = BinaryField::gf256();
+        let poly = Polynomial::random(0xa7, 3, field, &mut rand::thread_rng());
+        assert_eq!(Polynomial::interpolate(
+            (0..4).map(|party| (party, poly.eval(field.element(party as u16 + 1)))).collect(),
+            &field, |x| field.element(x as u16)
+        ), 0xa7);
+    }
+
+    #[test]
+    fn test_decode() {
+        let field = Field::new(251u16);
+        let poly = Polynomial::random(42u16, 2, field.clone(), &mut rand::thread_rng());
+        let shares: Vec<(usize, u16)> = (0..7).map(|party| (party, poly.eval(party as u16 + 1))).collect();
+
+        assert_eq!(Polynomial::decode(shares.clone(), 2, &field, |x| x as u16), Some((42, vec![])));
+
+        // n = 7 = 3t + 1 corrects t = 2 wrong shares
+        let mut corrupted = shares;
+        corrupted[1].1 = field.add(corrupted[1].1, 1);
+        corrupted[5].1 = field.add(corrupted[5].1, 250);
+        assert_eq!(Polynomial::decode(corrupted, 2, &field, |x| x as u16), Some((42, vec![1, 5])));
+    }
+
+    #[test]
+    fn test_decode_detects() {
+        let field = Field::new(251u16);
+        let poly = Polynomial::random(42u16, 2, field.clone(), &mut rand::thread_rng());
+        let mut shares: Vec<(usize, u16)> = (0..4).map(|party| (party, poly.eval(party as u16 + 1))).collect();
+
+        // with a single redundant share a wrong one is detected but cannot be corrected
+        shares[3].1 = field.add(shares[3].1, 1);
+        assert_eq!(Polynomial::decode(shares.clone(), 2, &field, |x| x as u16), None);
+        assert_eq!(Polynomial::decode(shares[..3].to_vec(), 2, &field, |x| x as u16).map(|(_, bad)| bad), Some(vec![]));
+        assert_eq!(Polynomial::decode(shares[..2].to_vec(), 2, &field, |x| x as u16), None);
+    }
+
+    #[test]
+    fn test_decode_binary() {
+        let field = BinaryField::gf256();
+        let poly = Polynomial::random(0xa7, 1, field, &mut rand::thread_rng());
+        let mut shares: Vec<(usize, u64)> = (0..4).map(|party| (party, poly.eval(field.element(party as u16 + 1)))).collect();
+        shares[2].1 ^= 0x10;
+
+        assert_eq!(Polynomial::decode(shares, 1, &field, |x| field.element(x as u16)), Some((0xa7, vec![2])));
+    }
+
+    #[test]
+    fn test_interpolate_subset() {
+        let field = Field::new(251u16);
+        let poly = Polynomial::random(42u16, 2, field.clone(), &mut rand::thread_rng());
+        let shares: Vec<(usize, u16)> = [6, 1, 4].iter().map(|&party| (party, poly.eval(party as u16 + 1))).collect();
+
+        assert_eq!(Polynomial::interpolate(shares, &field, |x| x as u16), 42);
+
+        // any 4 of 7 parties correct a wrong share of a polynomial of degree 1
+        let poly = Polynomial::random(42u16, 1, field.clone(), &mut rand::thread_rng());
+        let mut shares: Vec<(usize, u16)> = [0, 2, 3, 5].iter().map(|&party| (party, poly.eval(party as u16 + 1))).collect();
+        shares[2].1 = field.add(shares[2].1, 1);
+        assert_eq!(Polynomial::decode(shares, 1, &field, |x| x as u16), Some((42, vec![3])));
+    }
+}
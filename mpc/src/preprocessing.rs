@@ -0,0 +1,223 @@
+use crate::message::{take, DecodeError, WireShare, LEN_PREFIX};
+use crate::Error;
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+/// Version of the format produced by `Pool::encode`.
+pub const POOL_VERSION: u8 = 1;
+
+// version (u8) | double sharings (u64) | triples (u64)
+const HEADER_LEN: usize = 1 + 8 + 8;
+
+/// Shares of degree t and 2t of the same random value.
+pub type DoubleSharing<DataType> = (DataType, DataType);
+
+/// Shares of degree t of random `a`, `b` and of `a * b`.
+pub type Triple<DataType> = (DataType, DataType, DataType);
+
+/// One party's shares of circuit-independent randomness, produced ahead of time by
+/// `Party::preprocess` and consumed by `Party::run`.
+///
+/// A double sharing holds shares of a random `r` with degree t and 2t, a Beaver triple
+/// shares of random `a`, `b` and `c = a * b` with degree t. A pool is only valid together
+/// with the pools the other parties got from the same `preprocess` call, with the same
+/// party ids and threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pool<DataType> {
+    double_sharings: VecDeque<DoubleSharing<DataType>>,
+    triples: VecDeque<Triple<DataType>>,
+}
+
+impl<DataType: Clone> Pool<DataType> {
+
+    pub fn new() -> Self {
+        Pool {
+            double_sharings: VecDeque::new(),
+            triples: VecDeque::new(),
+        }
+    }
+
+    pub fn get_n_double_sharings(&self) -> usize {
+        self.double_sharings.len()
+    }
+
+    pub fn get_n_triples(&self) -> usize {
+        self.triples.len()
+    }
+
+    /// Appends the randomness of `other`, which is consumed after that of `self`.
+    pub fn extend(&mut self, other: Pool<DataType>) {
+        self.double_sharings.extend(other.double_sharings);
+        self.triples.extend(other.triples);
+    }
+
+    pub(crate) fn push_double_sharing(&mut self, s: DataType, t: DataType) {
+        self.double_sharings.push_back((s, t));
+    }
+
+    pub(crate) fn push_triple(&mut self, a: DataType, b: DataType, c: DataType) {
+        self.triples.push_back((a, b, c));
+    }
+
+    /// Removes the `n` oldest double sharings, or fails without removing any.
+    pub(crate) fn take_double_sharings(&mut self, n: usize) -> Result<Vec<DoubleSharing<DataType>>, Error> {
+        if n > self.double_sharings.len() {
+            return Err(Error::PoolExhausted { kind: "double sharings", needed: n, available: self.double_sharings.len() });
+        }
+        Ok(self.double_sharings.drain(..n).collect())
+    }
+
+    /// Removes the `n` oldest triples, or fails without removing any.
+    pub(crate) fn take_triples(&mut self, n: usize) -> Result<Vec<Triple<DataType>>, Error> {
+        if n > self.triples.len() {
+            return Err(Error::PoolExhausted { kind: "triples", needed: n, available: self.triples.len() });
+        }
+        Ok(self.triples.drain(..n).collect())
+    }
+
+    /// Moves the `double_sharings` oldest double sharings and `triples` oldest triples into a
+    /// new pool, or fails without removing any.
+    pub(crate) fn split_off(&mut self, double_sharings: usize, triples: usize) -> Result<Pool<DataType>, Error> {
+        if triples > self.triples.len() {
+            return Err(Error::PoolExhausted { kind: "triples", needed: triples, available: self.triples.len() });
+        }
+        Ok(Pool {
+            double_sharings: self.take_double_sharings(double_sharings)?.into(),
+            triples: self.take_triples(triples)?.into(),
+        })
+    }
+}
+
+impl<DataType: Clone> Default for Pool<DataType> {
+    fn default() -> Self {
+        Pool::new()
+    }
+}
+
+impl<DataType: WireShare + Clone> Pool<DataType> {
+
+    /// Encodes the pool to be stored: the version, the number of double sharings and of
+    /// triples, then the length-prefixed bytes of every share in order.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![POOL_VERSION];
+        bytes.extend_from_slice(&(self.double_sharings.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&(self.triples.len() as u64).to_be_bytes());
+
+        let shares = self.double_sharings.iter().flat_map(|(s, t)| vec![s, t])
+            .chain(self.triples.iter().flat_map(|(a, b, c)| vec![a, b, c]));
+        for share in shares {
+            let share = share.to_wire();
+            bytes.extend_from_slice(&(share.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&share);
+        }
+        bytes
+    }
+
+    /// Decodes a pool produced by `encode`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let version = take(bytes, 0, 1)?[0];
+        if version != POOL_VERSION {
+            return Err(DecodeError::UnsupportedPoolVersion(version));
+        }
+        let n_double_sharings = u64::from_be_bytes(take(bytes, 1, 8)?.try_into().unwrap());
+        let n_triples = u64::from_be_bytes(take(bytes, 9, 8)?.try_into().unwrap());
+
+        let mut offset = HEADER_LEN;
+        let mut next = || {
+            let len = u32::from_be_bytes(take(bytes, offset, LEN_PREFIX)?.try_into().unwrap()) as usize;
+            let share = DataType::from_wire(take(bytes, offset + LEN_PREFIX, len)?).ok_or(DecodeError::InvalidShare)?;
+            offset += LEN_PREFIX + len;
+            Ok(share)
+        };
+
+        // NOTE: the counts are untrusted, so the pool grows with the shares actually present
+        let mut pool = Pool::new();
+        for _ in 0..n_double_sharings {
+            pool.push_double_sharing(next()?, next()?);
+        }
+        for _ in 0..n_triples {
+            pool.push_triple(next()?, next()?, next()?);
+        }
+        if offset != bytes.len() {
+            return Err(DecodeError::Truncated { expected: offset, actual: bytes.len() });
+        }
+        Ok(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pool, POOL_VERSION};
+    use crate::{channel::{spawn_parties, tests::mul_circuit}, field::Field, message::DecodeError, Error};
+
+    fn pool() -> Pool<u16> {
+        let mut pool = Pool::new();
+        pool.push_double_sharing(1, 2);
+        pool.push_double_sharing(3, 4);
+        pool.push_triple(5, 6, 7);
+        pool
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(Pool::<u16>::decode(&pool().encode()), Ok(pool()));
+        assert_eq!(Pool::<u16>::decode(&Pool::<u16>::new().encode()), Ok(Pool::new()));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let mut bytes = pool().encode();
+        bytes[0] = POOL_VERSION + 1;
+        assert_eq!(Pool::<u16>::decode(&bytes), Err(DecodeError::UnsupportedPoolVersion(POOL_VERSION + 1)));
+
+        let bytes = pool().encode();
+        assert!(matches!(Pool::<u16>::decode(&bytes[..bytes.len() - 1]), Err(DecodeError::Truncated { .. })));
+        assert!(matches!(Pool::<u16>::decode(&[bytes.clone(), vec![0]].concat()), Err(DecodeError::Truncated { .. })));
+    }
+
+    #[test]
+    fn test_take_double_sharings() {
+        let mut pool = pool();
+        assert_eq!(pool.take_double_sharings(3), Err(Error::PoolExhausted { kind: "double sharings", needed: 3, available: 2 }));
+        assert_eq!(pool.get_n_double_sharings(), 2);
+        assert_eq!(pool.take_double_sharings(1), Ok(vec![(1, 2)]));
+        assert_eq!(pool.take_double_sharings(1), Ok(vec![(3, 4)]));
+        assert_eq!(pool.get_n_triples(), 1);
+    }
+
+    #[test]
+    fn test_split_off() {
+        let mut pool = pool();
+        assert_eq!(pool.split_off(1, 2), Err(Error::PoolExhausted { kind: "triples", needed: 2, available: 1 }));
+        assert_eq!(pool, self::pool());
+
+        let mut taken = pool.split_off(1, 1).unwrap();
+        assert_eq!(taken.take_double_sharings(1), Ok(vec![(1, 2)]));
+        assert_eq!(taken.take_triples(1), Ok(vec![(5, 6, 7)]));
+        assert_eq!((pool.get_n_double_sharings(), pool.get_n_triples()), (1, 0));
+    }
+
+    // Runs `mul_circuit` with every party's pool preprocessed and stored ahead of time,
+    // topped up with `extra` more double sharings.
+    fn run_preprocessed(double_sharings: usize, extra: usize) -> Vec<Result<Vec<u16>, Error>> {
+        let secrets = (0..3).map(|id| vec![id as u16 + 3]).collect();
+        spawn_parties(Field::new(251u16), mul_circuit(), secrets, 1, |_, tx| Box::new(tx), move |_, mut party| {
+            let stored = party.preprocess(double_sharings, 1)?.encode();
+            if extra > 0 {
+                let pool = party.preprocess(extra, 0)?;
+                party = party.with_pool(pool);
+            }
+
+            let pool = Pool::decode(&stored).unwrap();
+            party.with_pool(pool).run()
+        })
+    }
+
+    #[test]
+    fn test_run_preprocessed() {
+        assert_eq!(run_preprocessed(2, 0), vec![Ok(vec![60]); 3]);
+        assert_eq!(run_preprocessed(1, 1), vec![Ok(vec![60]); 3]);
+        assert_eq!(run_preprocessed(1, 0), vec![Err(Error::PoolExhausted { kind: "double sharings", needed: 2, available: 1 }); 3]);
+    }
+}
@@ -0,0 +1,35 @@
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// A cryptographically secure random number generator, usable as a trait object.
+///
+/// Implemented for every `RngCore + CryptoRng`, e.g. `rand::rngs::OsRng`,
+/// `rand::rngs::ThreadRng` or the seeded `ChaCha20Rng` returned by `party_rng`.
+pub trait CryptoRngCore: RngCore + CryptoRng {}
+
+impl<R: RngCore + CryptoRng> CryptoRngCore for R {}
+
+/// Deterministic RNG for `party` derived from a shared `seed`.
+///
+/// Every party gets its own ChaCha20 stream of the same key, so the randomness of
+/// different parties is independent, while re-running an election with the same seed
+/// reproduces every polynomial and mask, and therefore the whole transcript.
+/// Only meant for tests and benchmarks: anyone knowing the seed learns all shares.
+pub fn party_rng(seed: u64, party: usize) -> ChaCha20Rng {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    rng.set_stream(party as u64);
+    rng
+}
+
+#[cfg(test)]
+mod tests {
+    use super::party_rng;
+    use rand::RngCore;
+
+    #[test]
+    fn test_party_rng() {
+        assert_eq!(party_rng(7, 1).next_u64(), party_rng(7, 1).next_u64());
+        assert_ne!(party_rng(7, 1).next_u64(), party_rng(7, 2).next_u64());
+        assert_ne!(party_rng(7, 1).next_u64(), party_rng(8, 1).next_u64());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::Error;
+
 pub trait ShareReceiver<Msg> {
-    fn recv(&mut self) -> Msg;
-}
\ No newline at end of file
+    fn recv(&mut self) -> Result<Msg, Error>;
+}
@@ -1,3 +1,5 @@
+use crate::Error;
+
 pub trait ShareSender<Msg> {
-    fn send(&mut self, msg: Msg);
-}
\ No newline at end of file
+    fn send(&mut self, msg: Msg) -> Result<(), Error>;
+}
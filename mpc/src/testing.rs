@@ -0,0 +1,196 @@
+use crate::{
+    channel, circuit, field::FiniteField, gate, Error
+};
+
+/// Evaluates `circuit` in the clear, with `inputs[party][circuit_id]` as the value of
+/// each `Input` gate. Returns the values of the roots.
+pub fn evaluate<F: FiniteField>(circuit: &circuit::Circuit<F::Element>,
+                                field: &F,
+                                inputs: &[Vec<F::Element>]) -> Vec<F::Element> {
+    let mut circuit = circuit.clone();
+    for gate_id in circuit.traverse() {
+        let output = match circuit.get_gate(gate_id) {
+            gate::Gate::Input { ref party, ref circuit_id, output: _ } => {
+                inputs[*party][*circuit_id].clone()
+            }
+            gate::Gate::Add { ref first, ref second, output: _ } => {
+                field.add(circuit.get_gate(*first).get_output(), circuit.get_gate(*second).get_output())
+            }
+            gate::Gate::MulByConst { ref first, ref second, output: _ } => {
+                field.mul(circuit.get_gate(*first).get_output(), second.clone())
+            }
+            gate::Gate::AddConst { ref first, ref second, output: _ } => {
+                field.add(circuit.get_gate(*first).get_output(), second.clone())
+            }
+            gate::Gate::Sub { ref first, ref second, output: _ } => {
+                field.sub(circuit.get_gate(*first).get_output(), circuit.get_gate(*second).get_output())
+            }
+            gate::Gate::Neg { ref first, output: _ } => {
+                field.sub(field.zero(), circuit.get_gate(*first).get_output())
+            }
+            gate::Gate::Mul { ref first, ref second, output: _ } => {
+                field.mul(circuit.get_gate(*first).get_output(), circuit.get_gate(*second).get_output())
+            }
+            gate::Gate::Const { ref value, output: _ } => value.clone(),
+        };
+
+        circuit.get_gate_mut(gate_id).set_output(output);
+    }
+
+    circuit.get_roots().into_iter().map(|gate_id| circuit.get_gate(gate_id).get_output()).collect()
+}
+
+/// Runs `circuit` through one `Party` per entry of `inputs` over in-memory channels and
+/// asserts that every party's output equals the plaintext evaluation.
+/// Returns the agreed output.
+pub fn assert_matches_plaintext<F>(field: F,
+                                   circuit: circuit::Circuit<F::Element>,
+                                   inputs: Vec<Vec<F::Element>>,
+                                   threshold: usize) -> Result<Vec<F::Element>, Error>
+where F: FiniteField + Send + 'static,
+      F::Element: Send + 'static {
+    let expected = evaluate(&circuit, &field, &inputs);
+
+    let outputs = channel::run_parties(field, circuit, inputs, threshold)?;
+
+    for (party, output) in outputs.into_iter().enumerate() {
+        assert_eq!(output, expected, "output of party {} differs from plaintext evaluation", party);
+    }
+
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assert_matches_plaintext;
+    use crate::{circuit::Circuit, gate::Gate, field::Field};
+
+    use proptest::prelude::*;
+
+    const ORDER: u16 = 251;
+
+    // Each op is (kind, first, second, constant); operands are reduced modulo the
+    // number of gates built so far, so every op references an existing gate.
+    fn build_circuit(n_parties: usize, n_inputs: usize, ops: &[(u8, usize, usize, u16)], n_roots: usize) -> Circuit<u16> {
+        let mut circuit = Circuit::new(n_parties as u16);
+        for party in 0..n_parties {
+            for circuit_id in 0..n_inputs {
+                circuit.add(Gate::new_input(party, circuit_id));
+            }
+        }
+        for &(kind, first, second, constant) in ops {
+            let size = circuit.size();
+            let (first, second) = (first % size, second % size);
+            circuit.add(match kind % 7 {
+                0 => Gate::new_add(first, second),
+                1 => Gate::new_mul_by_const(first, constant),
+                2 => Gate::new_const(constant),
+                3 => Gate::new_add_const(first, constant),
+                4 => Gate::new_sub(first, second),
+                5 => Gate::new_neg(first),
+                _ => Gate::new_mul(first, second),
+            });
+        }
+        let size = circuit.size();
+        circuit.set_roots((size - n_roots.min(size)..size).collect());
+        circuit
+    }
+
+    fn party_count_and_threshold() -> impl Strategy<Value = (usize, usize)> {
+        (2usize..6).prop_flat_map(|n| (Just(n), 0..=(n - 1) / 2))
+    }
+
+    #[test]
+    fn test_big_field() {
+        use crate::field::primes::mersenne_127;
+        use num::BigUint;
+
+        let order = mersenne_127();
+        let mut circuit = Circuit::new(3);
+        let inputs: Vec<usize> = (0..3).map(|party| circuit.add(Gate::new_input(party, 0))).collect();
+        let product = circuit.add(Gate::new_mul(inputs[0], inputs[1]));
+        let product = circuit.add(Gate::new_mul(product, inputs[2]));
+        let negated = circuit.add(Gate::new_mul_by_const(product, order.clone() - 1u32));
+        circuit.set_roots(vec![product, negated]);
+
+        let big = BigUint::from(u64::MAX);
+        let secrets = vec![vec![big.clone()], vec![big.clone()], vec![BigUint::from(3u32)]];
+
+        let output = assert_matches_plaintext(Field::new(order.clone()), circuit, secrets, 1).unwrap();
+        assert_eq!(output[0], (big.clone() * big * 3u32) % order);
+    }
+
+    #[test]
+    fn test_const() {
+        // 1 - (a + 5) * (b - c) = 1 - 8 * 3
+        let mut circuit = Circuit::new(3);
+        let inputs: Vec<usize> = (0..3).map(|party| circuit.add_input(party, 0)).collect();
+        let sum = circuit.add_add_const(inputs[0], 5);
+        let difference = circuit.add_sub(inputs[1], inputs[2]);
+        let product = circuit.add_mul(sum, difference);
+        let negated = circuit.add_neg(product);
+        let one = circuit.add_const(1);
+        let root = circuit.add_add(one, negated);
+        circuit.set_roots(vec![product, root]);
+
+        let output = assert_matches_plaintext(Field::new(ORDER), circuit, vec![vec![3], vec![4], vec![1]], 1).unwrap();
+        assert_eq!(output, vec![24, ORDER - 23]);
+    }
+
+    #[test]
+    fn test_binary_field() {
+        use crate::field::BinaryField;
+
+        // (a AND b) XOR c, over GF(2^8)
+        let mut circuit = Circuit::new(5);
+        let inputs: Vec<usize> = (0..5).map(|party| circuit.add(Gate::new_input(party, 0))).collect();
+        let and = circuit.add(Gate::new_mul(inputs[0], inputs[1]));
+        let xor = circuit.add(Gate::new_add(and, inputs[2]));
+        circuit.set_roots(vec![and, xor]);
+
+        for &(a, b, c) in &[(0, 0, 0), (1, 1, 0), (1, 1, 1), (0, 1, 1)] {
+            let secrets = vec![vec![a], vec![b], vec![c], vec![0], vec![1]];
+            let output = assert_matches_plaintext(BinaryField::gf256(), circuit.clone(), secrets, 2).unwrap();
+            assert_eq!(output, vec![a & b, (a & b) ^ c]);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_random_circuit(
+            (n_parties, threshold) in party_count_and_threshold(),
+            n_inputs in 1usize..3,
+            ops in prop::collection::vec((any::<u8>(), any::<usize>(), any::<usize>(), 0..ORDER), 1..24),
+            n_roots in 1usize..4,
+            seed in prop::collection::vec(0..ORDER, 15),
+        ) {
+            let circuit = build_circuit(n_parties, n_inputs, &ops, n_roots);
+            let inputs = (0..n_parties)
+                .map(|party| (0..n_inputs).map(|i| seed[party * n_inputs + i]).collect())
+                .collect();
+
+            assert_matches_plaintext(Field::new(ORDER), circuit, inputs, threshold).unwrap();
+        }
+
+        #[test]
+        fn test_deep_mul_chain(
+            (n_parties, threshold) in party_count_and_threshold(),
+            depth in 1usize..16,
+            operands in prop::collection::vec(any::<usize>(), 16),
+            inputs in prop::collection::vec(1..ORDER, 5),
+        ) {
+            let mut circuit = Circuit::new(n_parties as u16);
+            let input_gates: Vec<usize> = (0..n_parties).map(|party| circuit.add(Gate::new_input(party, 0))).collect();
+            let root = (0..depth).fold(input_gates[0], |acc, layer| {
+                circuit.add(Gate::new_mul(acc, input_gates[operands[layer] % n_parties]))
+            });
+            circuit.set_roots(vec![root]);
+
+            let inputs = (0..n_parties).map(|party| vec![inputs[party]]).collect();
+
+            assert_matches_plaintext(Field::new(ORDER), circuit, inputs, threshold).unwrap();
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use num::{BigUint, One, Zero};
+use sha2::{Digest, Sha256};
+
+use crate::field::{primes, Field, FiniteField};
+
+/// Commitments letting the receivers of input shares check that they lie on a single
+/// polynomial of degree t, enabled with `Party::with_vss`.
+///
+/// The dealer shares each input along with a random blinding polynomial of the same degree,
+/// and commits to the coefficients of both, encoded as field elements. It sends the same
+/// commitments to every party, which echo their digest to each other: a party whose digest
+/// differs from the one received counts as complaining about the dealer.
+pub trait Commitments<F: FiniteField> {
+    /// Number of field elements a commitment to one pair of coefficients is encoded as.
+    fn width(&self) -> usize;
+
+    /// Commits to each pair of `coeffs` and `blinding` coefficients, lowest degree first,
+    /// returning `width` elements per pair.
+    fn commit(&self, coeffs: &[F::Element], blinding: &[F::Element]) -> Vec<F::Element>;
+
+    /// Whether `share` and `blinding` are the values at `x` of the polynomials committed to
+    /// by `commitments`. Malformed commitments verify nothing.
+    fn verify(&self, commitments: &[F::Element], x: &F::Element, share: &F::Element, blinding: &F::Element) -> bool;
+
+    /// Number of field elements a digest is encoded as.
+    fn digest_width(&self) -> usize;
+
+    /// Collision resistant digest of `commitments`, `digest_width` elements long.
+    fn digest(&self, commitments: &[F::Element]) -> Vec<F::Element>;
+}
+
+/// Pedersen's commitments g^a h^b to the coefficients a of a polynomial and b of its blinding
+/// polynomial over `Field<BigUint>` of prime order q, in the subgroup of order q of the
+/// integers modulo a 2048-bit prime p = kq + 1.
+///
+/// They hide the coefficients unconditionally, and bind the dealer as long as the discrete
+/// logarithm of h to the base g is unknown, which also takes q to be large. Nothing is up the
+/// sleeve: k is the first even number from 2^(2048 - bits of q) making p prime, g = 2^k and
+/// h = 3^k.
+#[derive(Debug, Clone)]
+pub struct Pedersen {
+    order: BigUint,
+    modulus: BigUint,
+    g: BigUint,
+    h: BigUint,
+    // base q digits of a group element
+    width: usize,
+}
+
+impl Pedersen {
+    // NOTE: k = 2^(2048 - bits of order) + 2 * offset, with the offset found offline
+    fn new(order: BigUint, offset: u32) -> Self {
+        let k = (BigUint::one() << (2048 - order.bits())) + BigUint::from(2 * offset);
+        let modulus = &k * &order + 1u32;
+        let g = BigUint::from(2u32).modpow(&k, &modulus);
+        let h = BigUint::from(3u32).modpow(&k, &modulus);
+
+        let mut width = 0;
+        let mut rest = &modulus - 1u32;
+        while !rest.is_zero() {
+            rest /= &order;
+            width += 1;
+        }
+
+        Pedersen { order, modulus, g, h, width }
+    }
+
+    /// For the field of order `primes::mersenne_127()`.
+    pub fn mersenne_127() -> Self {
+        Self::new(primes::mersenne_127(), 11)
+    }
+
+    /// For the field of order `primes::curve25519_scalar()`.
+    pub fn curve25519_scalar() -> Self {
+        Self::new(primes::curve25519_scalar(), 607)
+    }
+
+    // The `count` lowest base q digits of `c`.
+    fn digits(&self, mut c: BigUint, count: usize) -> Vec<BigUint> {
+        (0..count)
+            .map(|_| {
+                let digit = &c % &self.order;
+                c /= &self.order;
+                digit
+            })
+            .collect()
+    }
+
+    // NOTE: only the elements of the subgroup of order q are commitments, the others would
+    // not be bound by exponents reduced modulo q
+    fn decode(&self, digits: &[BigUint]) -> Option<BigUint> {
+        if digits.iter().any(|digit| digit >= &self.order) {
+            return None;
+        }
+        let c = digits.iter().rev().fold(BigUint::zero(), |acc, digit| acc * &self.order + digit);
+        (c < self.modulus && c.modpow(&self.order, &self.modulus).is_one()).then_some(c)
+    }
+
+    fn commit_one(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        self.g.modpow(a, &self.modulus) * self.h.modpow(b, &self.modulus) % &self.modulus
+    }
+}
+
+impl Commitments<Field<BigUint>> for Pedersen {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn commit(&self, coeffs: &[BigUint], blinding: &[BigUint]) -> Vec<BigUint> {
+        coeffs.iter().zip(blinding)
+            .flat_map(|(a, b)| self.digits(self.commit_one(a, b), self.width))
+            .collect()
+    }
+
+    fn verify(&self, commitments: &[BigUint], x: &BigUint, share: &BigUint, blinding: &BigUint) -> bool {
+        if !commitments.len().is_multiple_of(self.width) {
+            return false;
+        }
+
+        // g^f(x) h^r(x) = prod_j (g^a_j h^b_j)^(x^j), with the exponents reduced modulo the
+        // group order q
+        let mut expected = BigUint::one();
+        let mut x_power = BigUint::one();
+        for commitment in commitments.chunks(self.width) {
+            match self.decode(commitment) {
+                Some(c) => expected = expected * c.modpow(&x_power, &self.modulus) % &self.modulus,
+                None => return false,
+            }
+            x_power = x_power * x % &self.order;
+        }
+
+        self.commit_one(share, blinding) == expected
+    }
+
+    fn digest_width(&self) -> usize {
+        // 256 bits in base q digits, q being over 2^126
+        3
+    }
+
+    fn digest(&self, commitments: &[BigUint]) -> Vec<BigUint> {
+        let mut hasher = Sha256::new();
+        for commitment in commitments {
+            // NOTE: length prefixed, so that distinct sequences hash distinct strings
+            let bytes = commitment.to_bytes_le();
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        }
+
+        self.digits(BigUint::from_bytes_le(&hasher.finalize()), self.digest_width())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Commitments, Pedersen};
+    use crate::{
+        channel::{spawn_parties, BoxedSender, ChannelSender}, circuit::Circuit, field::{primes, Field},
+        message::Message, share_sender::ShareSender, Error
+    };
+    use num::BigUint;
+
+    // f(x) = 7 + 3x + 5x^2, blinded by r(x) = 2 + 9x + 4x^2
+    fn commit(pedersen: &Pedersen) -> Vec<BigUint> {
+        let coeffs = [7u32, 3, 5].map(BigUint::from);
+        let blinding = [2u32, 9, 4].map(BigUint::from);
+        pedersen.commit(&coeffs, &blinding)
+    }
+
+    fn eval(x: u64) -> (BigUint, BigUint) {
+        (BigUint::from(7 + 3 * x + 5 * x * x), BigUint::from(2 + 9 * x + 4 * x * x))
+    }
+
+    #[test]
+    fn test_verify() {
+        let pedersen = Pedersen::mersenne_127();
+        let commitments = commit(&pedersen);
+        assert_eq!(commitments.len(), 3 * pedersen.width());
+
+        for x in 1..6 {
+            let (share, blinding) = eval(x);
+            let x = BigUint::from(x);
+            assert!(pedersen.verify(&commitments, &x, &share, &blinding));
+            assert!(!pedersen.verify(&commitments, &x, &(&share + 1u32), &blinding));
+            assert!(!pedersen.verify(&commitments, &x, &share, &(&blinding + 1u32)));
+        }
+    }
+
+    #[test]
+    fn test_verify_malformed() {
+        let pedersen = Pedersen::mersenne_127();
+        let mut commitments = commit(&pedersen);
+        let (share, blinding) = eval(1);
+        let x = BigUint::from(1u32);
+
+        assert!(!pedersen.verify(&commitments[1..], &x, &share, &blinding));
+        commitments[0] = primes::mersenne_127();
+        assert!(!pedersen.verify(&commitments, &x, &share, &blinding));
+        commitments[0] = BigUint::from(3u32);
+        assert!(!pedersen.verify(&commitments, &x, &share, &blinding));
+    }
+
+    #[test]
+    fn test_digest() {
+        let pedersen = Pedersen::mersenne_127();
+        let mut commitments = commit(&pedersen);
+        let digest = pedersen.digest(&commitments);
+        assert_eq!(digest.len(), pedersen.digest_width());
+        assert_eq!(pedersen.digest(&commitments), digest);
+
+        commitments[0] += 1u32;
+        assert_ne!(pedersen.digest(&commitments), digest);
+    }
+
+    // Adds one to the first share of the messages of the given (round, recipient) pairs.
+    struct Corrupt(ChannelSender<Message<BigUint>>, Vec<(usize, usize)>);
+
+    impl ShareSender<Message<BigUint>> for Corrupt {
+        fn send(&mut self, msg: Message<BigUint>) -> Result<(), Error> {
+            let mut shares = msg.get_shares();
+            if self.1.contains(&(msg.get_round(), msg.get_to())) {
+                shares[0] = (&shares[0] + 1u32) % primes::mersenne_127();
+            }
+            self.0.send(Message::new(msg.get_from(), msg.get_to(), msg.get_round(), shares))
+        }
+    }
+
+    // Deals the given recipient a share of the input plus one, with commitments to match.
+    struct Equivocate(ChannelSender<Message<BigUint>>, usize, Pedersen);
+
+    impl ShareSender<Message<BigUint>> for Equivocate {
+        fn send(&mut self, msg: Message<BigUint>) -> Result<(), Error> {
+            let mut shares = msg.get_shares();
+            if (msg.get_round(), msg.get_to()) == (0, self.1) {
+                let pedersen = &self.2;
+                // the share, its blinding share, then the commitment to the constant terms
+                shares[0] = (&shares[0] + 1u32) % &pedersen.order;
+                let c = pedersen.decode(&shares[2..2 + pedersen.width]).unwrap();
+                let c = pedersen.digits(c * &pedersen.g % &pedersen.modulus, pedersen.width);
+                shares.splice(2..2 + pedersen.width, c);
+            }
+            self.0.send(Message::new(msg.get_from(), msg.get_to(), msg.get_round(), shares))
+        }
+    }
+
+    // Sums the inputs of `n_parties` with VSS, the last party sending through `wrap`.
+    fn run_vss<W>(n_parties: usize, wrap: W) -> Vec<Result<Vec<BigUint>, Error>>
+    where W: Fn(ChannelSender<Message<BigUint>>) -> BoxedSender<BigUint> + Send + Sync + 'static {
+        let mut circuit = Circuit::new(n_parties as u16);
+        let inputs: Vec<usize> = (0..n_parties).map(|party| circuit.add_input(party, 0)).collect();
+        let sum = inputs[1..].iter().fold(inputs[0], |acc, &input| circuit.add_add(acc, input));
+        circuit.set_roots(vec![sum]);
+
+        let secrets = (0..n_parties).map(|id| vec![BigUint::from(id + 2)]).collect();
+        spawn_parties(Field::new(primes::mersenne_127()), circuit, secrets, 1, move |id, tx| {
+            if id == n_parties - 1 { wrap(tx) } else { Box::new(tx) }
+        }, |_, party| party.with_vss(Pedersen::mersenne_127()).setup()?.run())
+    }
+
+    fn sum(total: u32) -> Vec<BigUint> {
+        vec![BigUint::from(total)]
+    }
+
+    #[test]
+    fn test_vss() {
+        // round 0 shares the inputs, round 1 carries the complaints and round 2 the answers
+        assert_eq!(run_vss(4, |tx| Box::new(tx)), vec![Ok(sum(14)); 4]);
+
+        // party 0 complains and gets its share revealed, which it needs as with n = 2t + 1 a
+        // wrong output share could not be corrected
+        let outputs = run_vss(3, |tx| Box::new(Corrupt(tx, vec![(0, 0)])));
+        assert_eq!(outputs, vec![Ok(sum(9)); 3]);
+
+        // the revealed shares are wrong too, so the last party's input counts as zero; its
+        // own output share is then wrong and gets corrected
+        let outputs = run_vss(4, |tx| Box::new(Corrupt(tx, vec![(0, 0), (2, 0), (2, 1), (2, 2)])));
+        assert_eq!(outputs[..3], vec![Ok(sum(9)); 3][..]);
+    }
+
+    #[test]
+    fn test_vss_echo() {
+        // party 0 gets a valid share of other commitments than the others, which it learns
+        // from the echoed digests and complains about, getting the share it should have had
+        let outputs = run_vss(3, |tx| Box::new(Equivocate(tx, 0, Pedersen::mersenne_127())));
+        assert_eq!(outputs, vec![Ok(sum(9)); 3]);
+    }
+}
@@ -1,9 +1,9 @@
-# Usage: ./run.sh <log_level> <n_voters> <threshold> <options>
+# Usage: ./run.sh <log_level> <n_voters> <threshold> <options> [field] [mode] [on_invalid] [max_choices] [weights]
 # after - taskkill cargo process
 
 cargo build
 
-cargo run -p voting-server -- $2 $3 $4 &
+cargo run -p voting-server -- $2 $3 $4 $5 $6 $7 $8 $9 &
 
 for (( i=0; i<$2; i++ ))
    do
@@ -12,22 +12,68 @@ use std::collections::HashMap;
 
 static GLOBAL_VOTERS_COUNT: AtomicUsize = AtomicUsize::new(0);
 
+// Fields known to the voting clients; the chosen one is announced to every client.
+const SUPPORTED_FIELDS: [&str; 5] = ["p251", "mersenne61", "mersenne127", "curve25519", "gf256"];
+const DEFAULT_FIELD: &str = "p251";
+
+// What the election reveals: a bit per option meeting the threshold, per-option counts,
+// or only the winner of a single-choice (plurality) or ranked (borda) ballot.
+const SUPPORTED_MODES: [&str; 4] = ["threshold", "tally", "plurality", "borda"];
+// What clients do with ballots failing the validity checks.
+const SUPPORTED_ON_INVALID: [&str; 2] = ["exclude", "abort"];
+const DEFAULT_ON_INVALID: &str = "exclude";
+
+// Modes which compare scores and therefore need a prime field.
+const RANKING_MODES: [&str; 2] = ["plurality", "borda"];
+const DEFAULT_MODE: &str = "threshold";
+
+// Largest count a field holds: the integers below its order, embedded by the clients through
+// 16 bits.
+fn field_capacity(field: &str) -> usize {
+    match field {
+        "p251" => 250,
+        "gf256" => 255,
+        _ => u16::MAX as usize,
+    }
+}
+
 #[derive(Clone)]
 struct VoteOptions {
     expected_voters: usize,
     vote_threshold: usize,
+    field: String,
+    mode: String,
+    on_invalid: String,
+    max_choices: usize,
+    weights: Vec<usize>,
     options: String,
 }
 
 fn initialize_client(mut stream: TcpStream, VOTE_OPTIONS: VoteOptions) {
 
-    stream.write(&(VOTE_OPTIONS.expected_voters as u32).to_be_bytes()).unwrap();
+    stream.write_all(&(VOTE_OPTIONS.expected_voters as u32).to_be_bytes()).unwrap();
+
+    stream.write_all(&(VOTE_OPTIONS.vote_threshold as u32).to_be_bytes()).unwrap();
+
+    stream.write_all(&(VOTE_OPTIONS.field.len() as u32).to_be_bytes()).unwrap();
+    stream.write_all(VOTE_OPTIONS.field.as_bytes()).unwrap();
 
-    stream.write(&(VOTE_OPTIONS.vote_threshold as u32).to_be_bytes()).unwrap();
+    stream.write_all(&(VOTE_OPTIONS.mode.len() as u32).to_be_bytes()).unwrap();
+    stream.write_all(VOTE_OPTIONS.mode.as_bytes()).unwrap();
 
-    stream.write(VOTE_OPTIONS.options.as_bytes()).unwrap();
+    stream.write_all(&(VOTE_OPTIONS.on_invalid.len() as u32).to_be_bytes()).unwrap();
+    stream.write_all(VOTE_OPTIONS.on_invalid.as_bytes()).unwrap();
 
-    let mut data = [0 as u8; 500];
+    stream.write_all(&(VOTE_OPTIONS.max_choices as u32).to_be_bytes()).unwrap();
+
+    for weight in &VOTE_OPTIONS.weights {
+        stream.write_all(&(*weight as u32).to_be_bytes()).unwrap();
+    }
+
+    stream.write_all(&(VOTE_OPTIONS.options.len() as u32).to_be_bytes()).unwrap();
+    stream.write_all(VOTE_OPTIONS.options.as_bytes()).unwrap();
+
+    let mut data = [0u8; 500];
     match stream.read(&mut data) {
         Ok(size) => {
             if from_utf8(&data[0..size]).unwrap() == "VOTED" {
@@ -59,12 +105,18 @@ fn read_message(info: &[u8], read_stream: &mut TcpStream) -> (usize, Vec<u8>) {
     (id, data)
 }
 
-fn process_message(msg: Vec<u8>, id: usize, write_streams: &HashMap<usize, TcpStream>) -> bool {
-    match write_streams.get(&id) {
-        Some(mut stream) => {
-            stream.write(&msg).unwrap();
+// Forwards `msg` to voter `id`. A voter who cannot be written to has left: it is dropped,
+// along with the later messages to it, and the others go on without it.
+fn process_message(msg: Vec<u8>, id: usize, write_streams: &mut HashMap<usize, Option<TcpStream>>) -> bool {
+    match write_streams.get_mut(&id) {
+        Some(Some(stream)) => {
+            if let Err(e) = stream.write_all(&msg) {
+                println!("Voter {} left: {}", id, e);
+                write_streams.insert(id, None);
+            }
             true
         },
+        Some(None) => true,
         None => {
             println!("Error process_packet");
             false
@@ -72,16 +124,19 @@ fn process_message(msg: Vec<u8>, id: usize, write_streams: &HashMap<usize, TcpSt
     }
 }
 
-fn proxy_data(mut read_stream: TcpStream, write_streams: HashMap<usize, TcpStream>) {
+fn proxy_data(mut read_stream: TcpStream, mut write_streams: HashMap<usize, Option<TcpStream>>) {
     let start_protocol_info = b"Proxy Opened!";
-    read_stream.write(start_protocol_info).unwrap();
+    if let Err(e) = read_stream.write_all(start_protocol_info) {
+        println!("Voter left before the protocol started: {}", e);
+        return;
+    }
 
-    let mut data = [0 as u8; std::mem::size_of::<u64>() * 2];
+    let mut data = [0u8; std::mem::size_of::<u64>() * 2];
 
     while match read_stream.read_exact(&mut data) {
         Ok(_) => {
             let (id, msg) = read_message(&data[..], &mut read_stream);
-            process_message(msg, id, &write_streams)
+            process_message(msg, id, &mut write_streams)
         },
         Err(_) => {
             println!("Channel closed");
@@ -97,42 +152,101 @@ fn main() {
             Ok(expected_voters) => expected_voters,
             _ => panic!("EXPECTED_VOTERS should be a non-negative integer!")
         },
-        None => panic!("Specify program arguments: <expected_voters> <vote_threshold> <vote_options>"),
+        None => panic!("Specify program arguments: <expected_voters> <vote_threshold> <vote_options> [field] [mode] [on_invalid] [max_choices] [weights]"),
     };
 
     let VOTE_THRESHOLD: usize = match env::args().collect::<Vec<String>>().get(2) {
         Some(vote_threshold) => match vote_threshold.parse::<usize>() {
-            Ok(vote_threshold) => vote_threshold,
-            _ => panic!("VOTE_THRESHOLD should be a non-negative integer!")
+            Ok(vote_threshold) if vote_threshold >= 1 => vote_threshold,
+            _ => panic!("VOTE_THRESHOLD should be a positive integer!")
         },
-        None => panic!("Specify program arguments: <expected_voters> <vote_threshold> <vote_options>"),
+        None => panic!("Specify program arguments: <expected_voters> <vote_threshold> <vote_options> [field] [mode] [on_invalid] [max_choices] [weights]"),
     };
 
     let OPTIONS: String = match env::args().collect::<Vec<String>>().get(3) {
         Some(options) => options,
-        None => panic!("Specify program arguments: <expected_voters> <vote_threshold> <vote_options>"),
+        None => panic!("Specify program arguments: <expected_voters> <vote_threshold> <vote_options> [field] [mode] [on_invalid] [max_choices] [weights]"),
     }.to_string();
 
+    let FIELD: String = match env::args().collect::<Vec<String>>().get(4) {
+        Some(field) if SUPPORTED_FIELDS.contains(&field.as_str()) => field.to_string(),
+        Some(field) => panic!("Unsupported field {}, expected one of: {}", field, SUPPORTED_FIELDS.join(", ")),
+        None => DEFAULT_FIELD.to_string(),
+    };
+
+    let MODE: String = match env::args().collect::<Vec<String>>().get(5) {
+        Some(mode) if SUPPORTED_MODES.contains(&mode.as_str()) => mode.to_string(),
+        Some(mode) => panic!("Unsupported mode {}, expected one of: {}", mode, SUPPORTED_MODES.join(", ")),
+        None => DEFAULT_MODE.to_string(),
+    };
+    let ON_INVALID: String = match env::args().collect::<Vec<String>>().get(6) {
+        Some(on_invalid) if SUPPORTED_ON_INVALID.contains(&on_invalid.as_str()) => on_invalid.to_string(),
+        Some(on_invalid) => panic!("Unsupported handling of invalid ballots {}, expected one of: {}", on_invalid, SUPPORTED_ON_INVALID.join(", ")),
+        None => DEFAULT_ON_INVALID.to_string(),
+    };
+
+    // how many options an approval ballot may pick, all of them by default
+    let MAX_CHOICES: usize = match env::args().collect::<Vec<String>>().get(7) {
+        Some(max_choices) => match max_choices.parse::<usize>() {
+            Ok(max_choices) => max_choices,
+            _ => panic!("MAX_CHOICES should be a non-negative integer!")
+        },
+        None => OPTIONS.split(',').count(),
+    };
+
+    // how many times each voter's ballot counts, by voter id, e.g. "3,1,1"
+    let WEIGHTS: Vec<usize> = match env::args().collect::<Vec<String>>().get(8) {
+        Some(weights) => match weights.split(',').map(|weight| weight.parse::<usize>()).collect::<Result<Vec<usize>, _>>() {
+            Ok(weights) if weights.len() == EXPECTED_VOTERS => weights,
+            _ => panic!("WEIGHTS should be {} comma separated non-negative integers!", EXPECTED_VOTERS)
+        },
+        None => vec![1; EXPECTED_VOTERS],
+    };
+
+    if RANKING_MODES.contains(&MODE.as_str()) && FIELD == "gf256" {
+        panic!("Mode {} compares scores and needs a prime field", MODE);
+    }
+
+    // the clients count up to the total weight, and in ranking modes compare differences of
+    // scores shifted to be nonnegative, which must all be distinct elements of the field
+    let number_of_options = OPTIONS.split(',').count();
+    let total_weight: usize = WEIGHTS.iter().sum();
+    let max_count = match MODE.as_str() {
+        "plurality" => 2 * total_weight,
+        "borda" => 2 * total_weight * number_of_options.saturating_sub(1),
+        _ => total_weight,
+    }.max(number_of_options);
+    if max_count > field_capacity(&FIELD) {
+        panic!("Field {} cannot count up to {}, choose a larger one or lower the weights", FIELD, max_count);
+    }
+
     let VOTE_OPTIONS = VoteOptions {
         expected_voters: EXPECTED_VOTERS,
         vote_threshold: VOTE_THRESHOLD,
+        field: FIELD,
+        mode: MODE,
+        on_invalid: ON_INVALID,
+        max_choices: MAX_CHOICES,
+        weights: WEIGHTS,
         options: OPTIONS,
     };
 
     let listener = TcpListener::bind("0.0.0.0:3333").unwrap();
-    println!("Server starting with options: number of voters: {};  vote threshold: {}; voting options: {}.", VOTE_OPTIONS.expected_voters, VOTE_OPTIONS.vote_threshold, VOTE_OPTIONS.options);
+    println!("Server starting with options: number of voters: {};  vote threshold: {}; field: {}; mode: {}; invalid ballots: {}; max choices: {}; weights: {:?}; voting options: {}.", VOTE_OPTIONS.expected_voters, VOTE_OPTIONS.vote_threshold, VOTE_OPTIONS.field, VOTE_OPTIONS.mode, VOTE_OPTIONS.on_invalid, VOTE_OPTIONS.max_choices, VOTE_OPTIONS.weights, VOTE_OPTIONS.options);
 
     let mut voters_streams: Vec<(TcpStream, usize)> = Vec::new();
 
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
+                // shares are small and latency bound, see `proxy_data`
+                stream.set_nodelay(true).unwrap();
                 if GLOBAL_VOTERS_COUNT.load(Ordering::SeqCst) >= EXPECTED_VOTERS {
                     println!("All voters have voted!");
 
                     let mut voters_streams_tmp: Vec<(TcpStream, usize)> = Vec::new();
                     for (next_stream, next_id) in &voters_streams {
-                        voters_streams_tmp.push((next_stream.try_clone().unwrap(), next_id.clone()));
+                        voters_streams_tmp.push((next_stream.try_clone().unwrap(), *next_id));
                     }
                     for (next_stream, next_id) in &voters_streams {
 
@@ -140,7 +254,7 @@ fn main() {
                         voters_streams_tmp.iter()
                             .filter(|(_, other_id)| next_id != other_id)
                             .for_each(|(other_stream, other_id)| {
-                                other_streams_map.insert(other_id.clone(), other_stream.try_clone().unwrap());
+                                other_streams_map.insert(*other_id, Some(other_stream.try_clone().unwrap()));
                             }
                         );
                         let next_stream_clone = next_stream.try_clone().unwrap();
@@ -149,7 +263,7 @@ fn main() {
                     }
                 } else {
                     // Receive party id
-                    let mut data = [0 as u8; 4];
+                    let mut data = [0u8; 4];
                     let id = match stream.try_clone().unwrap().read(&mut data) {
                         Ok(_) => {
                             let (id_bytes, _rest) = data.split_at(std::mem::size_of::<u32>());
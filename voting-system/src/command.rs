@@ -1,5 +1,7 @@
 use druid::Selector;
 
-pub(crate) const VOTE: Selector<Vec<bool>> = Selector::new("app.vote");
+pub(crate) const VOTE: Selector<Vec<u16>> = Selector::new("app.vote");
 
-pub(crate) const VOTE_OUTPUT: Selector<Vec<bool>> = Selector::new("app.vote_output");
+pub(crate) const VOTE_OUTPUT: Selector<Vec<u64>> = Selector::new("app.vote_output");
+
+pub(crate) const VOTE_ERROR: Selector<String> = Selector::new("app.vote_error");
@@ -23,6 +23,26 @@ impl Data for OptionsToggle {
     }
 }
 
+/// Options in the order the voter ranked them, best first.
+#[derive(Clone)]
+pub(crate) struct Ranking(pub Vec<usize>);
+
+impl Data for Ranking {
+    fn same(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+/// Per-option election results: threshold bits, or vote counts in tally mode.
+#[derive(Clone)]
+pub(crate) struct OptionsResult(pub Vec<u64>);
+
+impl Data for OptionsResult {
+    fn same(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
 pub(crate) struct VoteChoiceController {
     stream: TcpStream,
     vote_options: VoteOptions
@@ -69,20 +89,29 @@ impl VoteChoiceController {
             Handled::Yes
         } else if command.is(command::VOTE_OUTPUT) {
             data.is_computed = true;
-            data.options_result = OptionsToggle(command.get_unchecked(command::VOTE_OUTPUT).clone());
+            data.options_result = OptionsResult(command.get_unchecked(command::VOTE_OUTPUT).clone());
+            Handled::Yes
+        } else if command.is(command::VOTE_ERROR) {
+            data.error = Some(command.get_unchecked(command::VOTE_ERROR).clone());
             Handled::Yes
         } else {
             Handled::No
         }
     }
 
-    fn vote_wrapper(&mut self, sink: ExtEventSink, input: Vec<bool>) {
+    fn vote_wrapper(&mut self, sink: ExtEventSink, input: Vec<u16>) {
         let vote_options = self.vote_options.clone();
         let stream = self.stream.try_clone().unwrap();
         std::thread::spawn(move || {
-            let results: Vec<bool> = vote::vote(input, vote_options, stream)
-                .into_iter().map(|result| result != 0).collect();
-            sink.submit_command(command::VOTE_OUTPUT, results, Target::Auto).unwrap();
+            match vote::vote(input, vote_options, stream) {
+                Ok(results) => {
+                    sink.submit_command(command::VOTE_OUTPUT, results, Target::Auto).unwrap();
+                },
+                Err(e) => {
+                    println!("Error when voting: {}", e);
+                    sink.submit_command(command::VOTE_ERROR, e.to_string(), Target::Auto).unwrap();
+                }
+            }
         });
     }
 }
@@ -4,7 +4,6 @@ use druid::{
 };
 use std::net::{TcpStream};
 use std::io::{Read, Write};
-use std::str::from_utf8;
 use std::env;
 use std::convert::TryInto;
 
@@ -19,8 +18,23 @@ struct Params {
     is_confirmed: bool,
     options: String,
     options_toggle: controller::OptionsToggle,
+    ranking: controller::Ranking,
     is_computed: bool,
-    options_result: controller::OptionsToggle,
+    options_result: controller::OptionsResult,
+    error: Option<String>,
+}
+
+// Reads a string sent as its length in 4 bytes followed by its UTF-8 bytes.
+fn read_string(stream: &mut TcpStream) -> String {
+    let mut data = [0 as u8; std::mem::size_of::<u32>()];
+    if let Err(e) = stream.read_exact(&mut data) {
+        panic!("Failed to receive data: {}", e);
+    }
+    let mut string = vec![0u8; u32::from_be_bytes(data) as usize];
+    match stream.read_exact(&mut string) {
+        Ok(_) => String::from_utf8(string).unwrap(),
+        Err(e) => panic!("Failed to receive data: {}", e)
+    }
 }
 
 fn main() {
@@ -56,18 +70,42 @@ fn main() {
                 Err(e) => panic!("Failed to receive data: {}", e)
             };
 
-            let mut data = [0 as u8; 500];
-            let voting_options = match stream.read(&mut data) {
-                Ok(size) => {
-                	from_utf8(&data[0..size]).unwrap().to_string()
+            let field_name = read_string(&mut stream);
+            let field = match vote_options::ElectionField::from_name(&field_name) {
+                Some(field) => field,
+                None => panic!("Unsupported election field: {}", field_name)
+            };
+
+            let mode_name = read_string(&mut stream);
+            let mode = match vote_options::ElectionMode::from_name(&mode_name) {
+                Some(mode) => mode,
+                None => panic!("Unsupported election mode: {}", mode_name)
+            };
+
+            let on_invalid_name = read_string(&mut stream);
+            let on_invalid = match vote_options::InvalidBallots::from_name(&on_invalid_name) {
+                Some(on_invalid) => on_invalid,
+                None => panic!("Unsupported handling of invalid ballots: {}", on_invalid_name)
+            };
+            let max_choices = match stream.read_exact(&mut data) {
+                Ok(_) => {
+                    u32::from_be_bytes(data.try_into().unwrap()) as usize
                 },
                 Err(e) => panic!("Failed to receive data: {}", e)
             };
+            let weights = (0..number_of_voters).map(|_| match stream.read_exact(&mut data) {
+                Ok(_) => {
+                    u32::from_be_bytes(data.try_into().unwrap()) as usize
+                },
+                Err(e) => panic!("Failed to receive data: {}", e)
+            }).collect();
+
+            let voting_options = read_string(&mut stream);
 
             let number_of_options = voting_options.split(",").collect::<Vec<&str>>().len();
             let vote_options = vote_options::VoteOptions::new(
-                id, number_of_voters, vote_threshold, number_of_options
-            );
+                id, number_of_voters, vote_threshold, number_of_options, field, mode
+            ).with_ballot_checks(on_invalid, max_choices.min(number_of_options)).with_weights(weights);
 
             // Init UI
 		    let main_window = WindowDesc::new(move || ui_builder(stream, vote_options))
@@ -78,8 +116,10 @@ fn main() {
 		        is_confirmed: false,
 		        options: voting_options,
                 options_toggle: controller::OptionsToggle(vec![false; number_of_options]),
+                ranking: controller::Ranking(Vec::new()),
                 is_computed: false,
-                options_result: controller::OptionsToggle(vec![false; number_of_options]),
+                options_result: controller::OptionsResult(vec![0; number_of_options]),
+                error: None,
 		    };
 
 		    AppLauncher::with_window(main_window)
@@ -92,22 +132,65 @@ fn main() {
     }
 }
 
+fn result_label(data: &Params, i: usize, mode: vote_options::ElectionMode) -> String {
+    let option = data.options.split(",").collect::<Vec<&str>>()[i];
+    match mode {
+        vote_options::ElectionMode::Threshold => option.to_string(),
+        vote_options::ElectionMode::Tally => format!("{}: {}", option, data.options_result.0[i]),
+        vote_options::ElectionMode::Plurality | vote_options::ElectionMode::Borda if data.options_result.0[i] != 0 =>
+            format!("{} (winner)", option),
+        vote_options::ElectionMode::Plurality | vote_options::ElectionMode::Borda => option.to_string(),
+    }
+}
+
+fn choice_label(data: &Params, i: usize, mode: vote_options::ElectionMode) -> String {
+    let option = data.options.split(",").collect::<Vec<&str>>()[i];
+    match mode {
+        vote_options::ElectionMode::Borda => {
+            let position = data.ranking.0.iter().position(|&ranked| ranked == i).unwrap();
+            format!("{}. {}", position + 1, option)
+        },
+        _ => format!("-> {}", option),
+    }
+}
+
+/// Whether the selected options form a ballot allowed in `mode`.
+fn is_ballot_complete(data: &Params, mode: vote_options::ElectionMode) -> bool {
+    match mode {
+        vote_options::ElectionMode::Threshold | vote_options::ElectionMode::Tally => true,
+        vote_options::ElectionMode::Plurality => data.options_toggle.0.iter().filter(|&&toggled| toggled).count() == 1,
+        vote_options::ElectionMode::Borda => data.ranking.0.len() == data.options_toggle.0.len(),
+    }
+}
+
+/// Points given to each option: one per chosen option, or the Borda count of the ranking.
+fn ballot(data: &Params, mode: vote_options::ElectionMode) -> Vec<u16> {
+    match mode {
+        vote_options::ElectionMode::Borda => {
+            let mut points = vec![0; data.ranking.0.len()];
+            for (position, &option) in data.ranking.0.iter().enumerate() {
+                points[option] = (data.ranking.0.len() - 1 - position) as u16;
+            }
+            points
+        },
+        _ => data.options_toggle.0.iter().map(|&toggled| u16::from(toggled)).collect(),
+    }
+}
+
 fn ui_builder(stream: TcpStream, vote_options: vote_options::VoteOptions) -> impl Widget<Params> {
+    let mode = vote_options.get_mode();
     let buttons_group = (0..vote_options.get_number_of_options()).fold(
     	Flex::column(),
     	|column, i| column.with_child(
             Either::new(
                 move |data: &Params, _env: &Env| data.options_toggle.0[i],
                 Button::new(
-                    move |data: &Params, _env: &Env| {
-                        let mut label = "-> ".to_owned();
-                        label.push_str(data.options.split(",").collect::<Vec<&str>>()[i]);
-                        label
-                    }
+                    move |data: &Params, _env: &Env| choice_label(data, i, mode)
                 ).on_click(
                     move |_ctx: &mut EventCtx, data: &mut Params, _env| {
                         if !data.is_confirmed {
                             data.options_toggle.0[i] = false;
+                            data.ranking.0.retain(|&ranked| ranked != i);
                         }
                     }
                 ).border(Color::rgb(0.0, 0.0, 0.3), 2.0).padding(10.0).expand_width(),
@@ -116,7 +199,11 @@ fn ui_builder(stream: TcpStream, vote_options: vote_options::VoteOptions) -> imp
                 ).on_click(
                     move |_ctx: &mut EventCtx, data: &mut Params, _env| 
                         if !data.is_confirmed {
+                            if mode == vote_options::ElectionMode::Plurality {
+                                data.options_toggle.0.iter_mut().for_each(|toggled| *toggled = false);
+                            }
                             data.options_toggle.0[i] = true;
+                            data.ranking.0.push(i);
                         }
                 ).border(Color::rgb(0.0, 0.0, 0.3), 2.0).padding(10.0).expand_width(),
             )
@@ -129,29 +216,21 @@ fn ui_builder(stream: TcpStream, vote_options: vote_options::VoteOptions) -> imp
             Either::new(
                 move |data: &Params, _env: &Env| data.options_toggle.0[i],
                 Either::new(
-                    move |data: &Params, _env: &Env| data.options_result.0[i],
+                    move |data: &Params, _env: &Env| data.options_result.0[i] != 0,
                     Label::new(
-                        move |data: &Params, _env: &Env| {
-                            let mut label = "-> ".to_owned();
-                            label.push_str(data.options.split(",").collect::<Vec<&str>>()[i]);
-                            label
-                        }
+                        move |data: &Params, _env: &Env| format!("-> {}", result_label(data, i, mode))
                     ).center().expand_width().border(Color::rgb(0.0, 0.5, 0.0), 2.0).rounded(5.0).padding(10.0),
                     Label::new(
-                        move |data: &Params, _env: &Env| {
-                            let mut label = "-> ".to_owned();
-                            label.push_str(data.options.split(",").collect::<Vec<&str>>()[i]);
-                            label
-                        }
+                        move |data: &Params, _env: &Env| format!("-> {}", result_label(data, i, mode))
                     ).center().expand_width().border(Color::rgb(0.5, 0.0, 0.0), 2.0).rounded(5.0).padding(10.0),
                 ),
                 Either::new(
-                    move |data: &Params, _env: &Env| data.options_result.0[i],
+                    move |data: &Params, _env: &Env| data.options_result.0[i] != 0,
                     Label::new(
-                        move |data: &Params, _env: &Env| data.options.split(",").collect::<Vec<&str>>()[i].to_string()
+                        move |data: &Params, _env: &Env| result_label(data, i, mode)
                     ).center().expand_width().center().border(Color::rgb(0.0, 0.5, 0.0), 2.0).rounded(5.0).padding(10.0),
                     Label::new(
-                        move |data: &Params, _env: &Env| data.options.split(",").collect::<Vec<&str>>()[i].to_string()
+                        move |data: &Params, _env: &Env| result_label(data, i, mode)
                     ).center().expand_width().center().border(Color::rgb(0.5, 0.0, 0.0), 2.0).rounded(5.0).padding(10.0),
 
                 ),
@@ -160,19 +239,25 @@ fn ui_builder(stream: TcpStream, vote_options: vote_options::VoteOptions) -> imp
     );
 
     let to_vote_section = Flex::column()
-        .with_child(Label::new(|data: &Params, _env: &Env| {
-            if data.is_confirmed {
+        .with_child(Label::new(move |data: &Params, _env: &Env| {
+            if let Some(error) = &data.error {
+                format!("Voting failed: {}", error)
+            } else if data.is_confirmed {
                 "Voted, wait to compute the result!".to_string()
             } else {
-                "Options:".to_string()
+                match mode {
+                    vote_options::ElectionMode::Plurality => "Pick one option:".to_string(),
+                    vote_options::ElectionMode::Borda => "Rank all options, best first:".to_string(),
+                    _ => "Options:".to_string(),
+                }
             }
         }).padding(10.0))
         .with_child(buttons_group)
         .with_child(Button::new("Confirm votes").on_click(
             move |ctx: &mut EventCtx, data: &mut Params, _env: &Env| {
-                if !data.is_confirmed {
+                if !data.is_confirmed && is_ballot_complete(data, mode) {
                     data.is_confirmed = true;
-                    ctx.submit_command(command::VOTE.with(data.options_toggle.0.clone()))
+                    ctx.submit_command(command::VOTE.with(ballot(data, mode)))
                 }
             }
         ));
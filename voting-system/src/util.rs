@@ -1,57 +1,312 @@
-use itertools::Itertools;
+use std::fmt;
+
+use log::info;
 
 use mpc::{
-    circuit::Circuit,
-    gate::Gate,
+    builder::{Builder, Wire},
+    circuit::{Circuit, CircuitError, optimize::optimize},
+    field::FiniteField,
 };
 
-pub(crate) fn generate_circuit(number_of_voters: usize, vote_threshold: usize, number_of_options: usize, group_order: u16) -> Circuit<u16> {
-    let minus_one = group_order - 1;
-
-    let mut circuit = Circuit::new(number_of_voters as u16);
-
-    let roots = (0..number_of_options).map(|option_id| {
-        let inputs: Vec<usize> = (0..number_of_voters).map(|i| circuit.add(Gate::<u16>::new_input(i, option_id))).collect();
-
-        let last_and_gates: Vec<usize> = inputs.into_iter().combinations(vote_threshold)
-            .map(
-                |subset| subset.clone().into_iter().reduce(
-                    |acc, item| circuit.add(Gate::new_mul(acc, item))
-                )
-            ).map(Option::unwrap).collect();
-
-        let mul_last_gates: Vec<usize> = (1..last_and_gates.len() + 1).map(
-                |l| last_and_gates.clone().into_iter().combinations(l).map(
-                    |subset| {
-                        let mul_subset = subset.into_iter().reduce(
-                                |acc, item| circuit.add(Gate::new_mul(acc, item))
-                            ).unwrap();
-                        if l % 2 == 0 {
-                            circuit.add(Gate::new_mul_by_const(mul_subset, minus_one))
-                        } else {
-                            mul_subset
-                        }
-                    }
-                ).collect::<Vec<_>>()
-            ).flatten()
-            .collect();
+use crate::vote_options::{ElectionMode, InvalidBallots};
+
+/// Why no circuit can count an election in the given field.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CountingError {
+    /// Counting needs the integers from one up to this one to be distinct nonzero elements,
+    /// embedded through `u16`.
+    FieldTooSmall(usize),
+    /// Comparing scores needs a prime field.
+    NotPrimeField,
+    /// Threshold elections need a vote threshold of at least one.
+    ZeroThreshold,
+    InvalidCircuit(CircuitError),
+}
+
+impl fmt::Display for CountingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CountingError::FieldTooSmall(max_count) => write!(f, "field is too small to count up to {}", max_count),
+            CountingError::NotPrimeField => write!(f, "comparing scores needs a prime field"),
+            CountingError::ZeroThreshold => write!(f, "vote threshold must be at least 1"),
+            CountingError::InvalidCircuit(e) => write!(f, "invalid circuit: {}", e),
+        }
+    }
+}
+
+impl From<CircuitError> for CountingError {
+    fn from(e: CircuitError) -> Self {
+        CountingError::InvalidCircuit(e)
+    }
+}
+
+/// Builds the election circuit for voters whose ballots count `weights[i]` times. Its roots are
+/// one result per option, followed by one bit per voter which is one if that voter's ballot is valid.
+///
+/// With `InvalidBallots::Exclude` invalid ballots are left out of the results, with
+/// `InvalidBallots::Abort` a single invalid ballot turns every result into zero.
+pub(crate) fn generate_circuit<F: FiniteField>(weights: &[usize], vote_threshold: usize, number_of_options: usize, max_choices: usize, mode: ElectionMode, on_invalid: InvalidBallots, field: &F) -> Result<Circuit<F::Element>, CountingError> {
+    let number_of_voters = weights.len();
+    let total_weight: usize = weights.iter().sum();
+    let max_count = match mode {
+        // differences of scores are compared, shifted to be nonnegative
+        ElectionMode::Plurality => 2 * total_weight,
+        ElectionMode::Borda => 2 * total_weight * number_of_options.saturating_sub(1),
+        ElectionMode::Threshold | ElectionMode::Tally => total_weight,
+    }.max(number_of_options);
+    // the integers below the order embed as distinct elements, as bit patterns in binary fields
+    let capacity = field.order_minus_one_bits().into_iter()
+        .fold(0, |acc: usize, bit| (2 * acc + bit as usize).min(u16::MAX as usize));
+    if max_count > capacity {
+        return Err(CountingError::FieldTooSmall(max_count));
+    }
+    if matches!(mode, ElectionMode::Plurality | ElectionMode::Borda) && field.add(field.one(), field.one()) == field.zero() {
+        return Err(CountingError::NotPrimeField);
+    }
+    if mode == ElectionMode::Threshold && vote_threshold == 0 {
+        return Err(CountingError::ZeroThreshold);
+    }
+
+    let builder = Builder::new(number_of_voters as u16, field.clone());
+
+    let ballots: Vec<Vec<Wire<F>>> = (0..number_of_voters).map(|i| {
+        (0..number_of_options).map(|option_id| builder.input(i, option_id)).collect()
+    }).collect();
 
-            mul_last_gates.into_iter().reduce(|acc, item| circuit.add(Gate::new_add(acc, item))).unwrap()
+    let valid: Vec<Wire<F>> = ballots.iter()
+        .map(|ballot| ballot_validity(&builder, ballot, max_choices, mode))
+        .collect();
+
+    let ballots: Vec<Vec<Wire<F>>> = match on_invalid {
+        InvalidBallots::Exclude => ballots.iter().zip(&valid).map(|(ballot, &valid)| {
+            ballot.iter().map(|&x| x * valid).collect()
+        }).collect(),
+        InvalidBallots::Abort => ballots,
+    };
+
+    let scores: Vec<Wire<F>> = (0..number_of_options).map(|option_id| {
+        let inputs: Vec<Wire<F>> = ballots.iter().map(|ballot| ballot[option_id]).collect();
+        count_votes(&builder, &inputs, weights)
     }).collect();
-    
-    println!("Circuit size: {}", circuit.size());
-    circuit.set_roots(roots);
-    circuit
+
+    let results: Vec<Wire<F>> = match mode {
+        ElectionMode::Threshold => {
+            let points: Vec<F::Element> = (0..=total_weight).map(|c| field.element(c as u16)).collect();
+            let indicator = indicator_coefficients(&points, |c| c >= vote_threshold, field);
+            scores.into_iter().map(|count| evaluate_polynomial(&builder, count, &indicator)).collect()
+        },
+        ElectionMode::Tally => scores,
+        // a plurality ballot gives one point to a single option
+        ElectionMode::Plurality => winner(&builder, &scores, total_weight),
+        // a ranking gives `number_of_options - 1` points to the first option, down to zero for the last one
+        ElectionMode::Borda => winner(&builder, &scores, total_weight * (number_of_options - 1)),
+    };
+
+    let results: Vec<Wire<F>> = match on_invalid {
+        InvalidBallots::Exclude => results,
+        InvalidBallots::Abort => {
+            let all_valid = builder.product(valid.clone());
+            results.into_iter().map(|result| result * all_valid).collect()
+        },
+    };
+
+    results.into_iter().chain(valid).for_each(|wire| builder.output(wire));
+    let (circuit, report) = optimize(&builder.build(), field)?;
+    info!("Circuit: {}", report);
+    Ok(circuit)
+}
+
+/// Adds gates computing one if `ballot` is allowed in `mode` and zero otherwise.
+///
+/// Each constraint is a value which is zero exactly when it holds: `x * (x - 1)` for every
+/// approval, `sum - 1` for a plurality ballot, and for a ranking the difference between its
+/// power sums and those of `0..number_of_options`, which match only for a permutation.
+fn ballot_validity<'a, F: FiniteField>(builder: &'a Builder<F>, ballot: &[Wire<'a, F>], max_choices: usize, mode: ElectionMode) -> Wire<'a, F> {
+    let field = builder.field();
+
+    let mut constraints: Vec<Wire<F>> = Vec::new();
+    match mode {
+        ElectionMode::Borda => {
+            let mut powers = ballot.to_vec();
+            for m in 1..=ballot.len() {
+                if m > 1 {
+                    powers = powers.iter().zip(ballot).map(|(&power, &x)| power * x).collect();
+                }
+                let expected = (0..ballot.len()).fold(field.zero(), |acc, score| {
+                    let power = (0..m).fold(field.one(), |power, _| field.mul(power, field.element(score as u16)));
+                    field.add(acc, power)
+                });
+                constraints.push(builder.sum(powers.clone()).add_const(field.sub(field.zero(), expected)));
+            }
+        },
+        _ => {
+            constraints.extend(ballot.iter().map(|&x| x * x - x));
+            if mode == ElectionMode::Plurality {
+                constraints.push(count_votes(builder, ballot, &vec![1; ballot.len()]) - 1);
+            }
+        },
+    }
+
+    let violated: Vec<Wire<F>> = constraints.into_iter().map(|constraint| is_nonzero(builder, constraint)).collect();
+    let mut invalid = violated.into_iter().reduce(|acc, item| acc.or(item)).unwrap();
+
+    if matches!(mode, ElectionMode::Threshold | ElectionMode::Tally) && max_choices < ballot.len() {
+        // the count is only meaningful for approvals in {0, 1}, and `or` ignores it otherwise
+        let count = count_votes(builder, ballot, &vec![1; ballot.len()]);
+        let points: Vec<F::Element> = (0..=ballot.len()).map(|c| field.element(c as u16)).collect();
+        let too_many = evaluate_polynomial(builder, count, &indicator_coefficients(&points, |c| c > max_choices, field));
+        invalid = invalid.or(too_many);
+    }
+
+    !invalid
+}
+
+/// Adds gates computing `x^(order - 1)`, which is zero for zero and one otherwise.
+fn is_nonzero<'a, F: FiniteField>(builder: &'a Builder<F>, x: Wire<'a, F>) -> Wire<'a, F> {
+    // square-and-multiply; the leading bit is always set
+    builder.field().order_minus_one_bits().into_iter().skip(1).fold(x, |acc, bit| {
+        let acc = acc * acc;
+        if bit {
+            acc * x
+        } else {
+            acc
+        }
+    })
+}
+
+/// Adds gates computing `field.element(c)`, where `c` is the number of `inputs` equal to one,
+/// each counted `weights[i]` times.
+fn count_votes<'a, F: FiniteField>(builder: &'a Builder<F>, inputs: &[Wire<'a, F>], weights: &[usize]) -> Wire<'a, F> {
+    let field = builder.field();
+    if field.add(field.one(), field.one()) != field.zero() {
+        return builder.sum(inputs.iter().zip(weights).map(|(&vote, &weight)| {
+            if weight == 1 {
+                vote
+            } else {
+                vote * weight as u16
+            }
+        }));
+    }
+
+    // in characteristic 2 `Add` is XOR, so the votes go through a binary counter: a vote is
+    // added with half adders (sum = a + b, carry = a * b) at every set bit of its weight
+    let mut bits: Vec<Wire<F>> = Vec::new();
+    let mut total = 0usize;
+    for (&vote, &weight) in inputs.iter().zip(weights) {
+        for position in (0..usize::BITS as usize).filter(|position| (weight >> position) & 1 == 1) {
+            while bits.len() < position {
+                bits.push(builder.constant(field.zero()));
+            }
+            let mut carry = vote;
+            for bit in bits.iter_mut().skip(position) {
+                let sum = *bit + carry;
+                carry = *bit * carry;
+                *bit = sum;
+            }
+            // the count so far needs that many bits; otherwise the carry is zero
+            total += 1 << position;
+            if bits.len() < usize::BITS as usize - total.leading_zeros() as usize {
+                bits.push(carry);
+            }
+        }
+    }
+
+    builder.sum(bits.into_iter().enumerate().map(|(j, bit)| bit * (1 << j) as u16))
+}
+
+/// Adds gates computing, for each option, one if it has the highest score and zero otherwise.
+/// Ties go to the option with the lowest index, so exactly one option wins.
+fn winner<'a, F: FiniteField>(builder: &'a Builder<F>, scores: &[Wire<'a, F>], max_score: usize) -> Vec<Wire<'a, F>> {
+    // NOTE: `generate_circuit` checked that the field is prime and holds every difference,
+    // embedded through u16
+    let field = builder.field();
+
+    // differences of scores lie in -max_score..=max_score, points[d] is `d - max_score`
+    let points: Vec<F::Element> = (0..=2 * max_score)
+        .map(|d| field.sub(field.element(d as u16), field.element(max_score as u16)))
+        .collect();
+    let at_least = indicator_coefficients(&points, |d| d >= max_score, field);
+
+    // beats[i][j] is one if option `i` ranks above option `j`
+    let mut beats = vec![vec![None; scores.len()]; scores.len()];
+    for i in 0..scores.len() {
+        for j in i + 1..scores.len() {
+            let i_beats_j = evaluate_polynomial(builder, scores[i] - scores[j], &at_least);
+            beats[i][j] = Some(i_beats_j);
+            beats[j][i] = Some(!i_beats_j);
+        }
+    }
+
+    beats.into_iter()
+        .map(|row| builder.product(row.into_iter().flatten()))
+        .collect()
+}
+
+/// Coefficients, lowest degree first, of the polynomial which is one on `points[i]` if
+/// `is_one(i)` and zero on the other points.
+fn indicator_coefficients<F: FiniteField>(points: &[F::Element], is_one: impl Fn(usize) -> bool, field: &F) -> Vec<F::Element> {
+    // prod(x - points[j]); dividing it by (x - points[s]) gives the numerator of the Lagrange
+    // basis polynomial of `points[s]` in linear time
+    let mut master = vec![field.one()];
+    for point in points {
+        master.insert(0, field.zero());
+        for k in 0..master.len() - 1 {
+            master[k] = field.sub(master[k].clone(), field.mul(point.clone(), master[k + 1].clone()));
+        }
+    }
+
+    let mut coefficients = vec![field.zero(); points.len()];
+    for s in (0..points.len()).filter(|&s| is_one(s)) {
+        let mut basis = vec![field.zero(); points.len()];
+        let mut carry = field.zero();
+        for k in (0..points.len()).rev() {
+            carry = field.add(master[k + 1].clone(), field.mul(points[s].clone(), carry));
+            basis[k] = carry.clone();
+        }
+        let denominator = points.iter().enumerate().filter(|(j, _)| *j != s).fold(field.one(), |acc, (_, point)| {
+            field.mul(acc, field.sub(points[s].clone(), point.clone()))
+        });
+
+        let scale = field.inv(denominator);
+        for (coefficient, term) in coefficients.iter_mut().zip(basis) {
+            *coefficient = field.add(coefficient.clone(), field.mul(term, scale.clone()));
+        }
+    }
+    coefficients
+}
+
+/// Adds gates computing `sum(coefficients[k] * x^k)`.
+fn evaluate_polynomial<'a, F: FiniteField>(builder: &'a Builder<F>, x: Wire<'a, F>, coefficients: &[F::Element]) -> Wire<'a, F> {
+    let field = builder.field();
+
+    // powers[k - 1] is x^k; x^k = x^(k/2) * x^(k - k/2) keeps the multiplicative depth logarithmic
+    let mut powers = vec![x];
+    for k in 2..coefficients.len() {
+        let power = powers[k / 2 - 1] * powers[k - k / 2 - 1];
+        powers.push(power);
+    }
+
+    let terms = builder.sum((1..coefficients.len())
+        .filter(|&k| k == 1 || coefficients[k] != field.zero())
+        .map(|k| powers[k - 1].mul_by_const(coefficients[k].clone())));
+    if coefficients[0] != field.zero() {
+        terms.add_const(coefficients[0].clone())
+    } else {
+        terms
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use mpc::{
-        circuit::Circuit,
-        gate::Gate,
-        field::Field,
-    };
-    use super::generate_circuit;
+    use mpc::{testing::{assert_matches_plaintext, evaluate}, field::{Field, BinaryField, FiniteField}};
+    use super::{generate_circuit, CountingError};
+    use crate::vote_options::{ElectionMode, InvalidBallots};
+
+    // results followed by the validity bits of `number_of_voters` valid ballots
+    fn all_valid<T: Clone>(mut results: Vec<T>, one: T, number_of_voters: usize) -> Vec<T> {
+        results.extend(vec![one; number_of_voters]);
+        results
+    }
 
     #[test]
     fn test_circuit_1() {
@@ -61,9 +316,9 @@ mod tests {
         let group_order: u16 = 251;
         let input = vec![vec![0, 0], vec![1, 0], vec![1, 1], vec![1, 0], vec![1, 0]];
 
-        let circuit = generate_circuit(number_of_voters, vote_threshold, number_of_options, group_order);
+        let circuit = generate_circuit(&vec![1; number_of_voters], vote_threshold, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(group_order)).unwrap();
 
-        assert_eq!(run(circuit, 251, input), vec![1, 0])
+        assert_eq!(assert_matches_plaintext(Field::new(group_order), circuit, input, 2).unwrap(), all_valid(vec![1, 0], 1, number_of_voters))
     }
 
     #[test]
@@ -74,9 +329,9 @@ mod tests {
         let group_order: u16 = 251;
         let input = vec![vec![0, 0, 0], vec![1, 0, 0], vec![1, 1, 0], vec![1, 1, 1]];
 
-        let circuit = generate_circuit(number_of_voters, vote_threshold, number_of_options, group_order);
+        let circuit = generate_circuit(&vec![1; number_of_voters], vote_threshold, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(group_order)).unwrap();
 
-        assert_eq!(run(circuit, 251, input), vec![1, 1, 0])
+        assert_eq!(assert_matches_plaintext(Field::new(group_order), circuit, input, 1).unwrap(), all_valid(vec![1, 1, 0], 1, number_of_voters))
     }
 
     #[test]
@@ -87,48 +342,269 @@ mod tests {
         let group_order: u16 = 251;
         let input = vec![vec![1, 1, 1, 0, 0], vec![1, 1, 0, 0, 0], vec![1, 0, 0, 0, 0]];
 
-        let circuit = generate_circuit(number_of_voters, vote_threshold, number_of_options, group_order);
+        let circuit = generate_circuit(&vec![1; number_of_voters], vote_threshold, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(group_order)).unwrap();
 
-        assert_eq!(run(circuit, 251, input), vec![1, 1, 0, 0, 0])
+        assert_eq!(assert_matches_plaintext(Field::new(group_order), circuit, input, 1).unwrap(), all_valid(vec![1, 1, 0, 0, 0], 1, number_of_voters))
     }
 
+    #[test]
+    fn test_circuit_big_field() {
+        let number_of_voters = 3;
+        let vote_threshold = 2;
+        let number_of_options = 2;
+        let group_order = mpc::field::primes::curve25519_scalar();
+        let input = vec![vec![1, 0], vec![0, 0], vec![1, 1]].into_iter()
+            .map(|votes: Vec<u32>| votes.into_iter().map(num::BigUint::from).collect())
+            .collect();
 
-    fn run(cir: Circuit<u16>, field_order: u16, input: Vec<Vec<u16>>) -> Vec<u16> {
+        let circuit = generate_circuit(&vec![1; number_of_voters], vote_threshold, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(group_order.clone())).unwrap();
 
-        let field = Field::<u16>::new(field_order);
+        assert_eq!(assert_matches_plaintext(Field::new(group_order), circuit, input, 1).unwrap(), all_valid(vec![1u32.into(), 0u32.into()], 1u32.into(), number_of_voters))
+    }
 
-        let mut circuit = cir.clone();
-        for gate_id in circuit.traverse() {
+    #[test]
+    fn test_circuit_binary_field() {
+        let number_of_voters = 5;
+        let vote_threshold = 3;
+        let number_of_options = 2;
+        let field = BinaryField::gf256();
+        let input = vec![vec![0, 0], vec![1, 0], vec![1, 1], vec![1, 0], vec![1, 0]];
 
-            let output = match circuit.get_gate(gate_id) {
-                Gate::Input { ref party, ref circuit_id, output: _ } => {
-                    input[*party][*circuit_id]
-                }
-                Gate::Add { ref first, ref second, output: _ } => {
-                    process_add(circuit.get_gate(*first), circuit.get_gate(*second), &field)
-                }
-                Gate::MulByConst { ref first, ref second, output: _ } => {
-                    process_mul_by_const(circuit.get_gate(*first), second.clone(), &field)
-                }
-                Gate::Mul { ref first, ref second, output: _ } => {
-                    process_mul(circuit.get_gate(*first), circuit.get_gate(*second), &field)
-                }
-            };
+        let circuit = generate_circuit(&vec![1; number_of_voters], vote_threshold, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Exclude, &field).unwrap();
+
+        assert_eq!(assert_matches_plaintext(field, circuit, input, 2).unwrap(), all_valid(vec![1, 0], 1, number_of_voters))
+    }
+
+    #[test]
+    fn test_circuit_many_voters() {
+        let number_of_voters = 10;
+        let vote_threshold = 5;
+        let number_of_options = 2;
+        let group_order: u16 = 251;
+        let input = (0..number_of_voters).map(|i| vec![(i < 5) as u16, (i < 4) as u16]).collect();
+
+        let circuit = generate_circuit(&vec![1; number_of_voters], vote_threshold, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(group_order)).unwrap();
+        // ballot checks included, the circuit grows linearly with the number of voters
+        assert!(circuit.size() < 60 * number_of_voters);
+
+        assert_eq!(assert_matches_plaintext(Field::new(group_order), circuit, input, 4).unwrap(), all_valid(vec![1, 0], 1, number_of_voters))
+    }
 
-            circuit.get_gate_mut(gate_id).set_output(output);
+    fn check_every_count<F: FiniteField>(field: F) {
+        let number_of_voters = 7;
+        for vote_threshold in 1..=number_of_voters + 1 {
+            let circuit = generate_circuit(&vec![1; number_of_voters], vote_threshold, 1, 1, ElectionMode::Threshold, InvalidBallots::Exclude, &field).unwrap();
+            for count in 0..=number_of_voters {
+                let input: Vec<Vec<F::Element>> = (0..number_of_voters)
+                    .map(|i| vec![if i < count { field.one() } else { field.zero() }])
+                    .collect();
+                let expected = if count >= vote_threshold { field.one() } else { field.zero() };
+                assert_eq!(evaluate(&circuit, &field, &input)[..1], [expected], "{} votes, threshold {}", count, vote_threshold);
+            }
         }
-        circuit.get_roots().into_iter().map(|gate_id| circuit.get_gate(gate_id).get_output()).collect()
     }
 
-    fn process_add(first: &Gate<u16>, second: &Gate<u16>, field: &Field<u16>) -> u16 {
-        field.add(first.get_output(), second.get_output())
+    #[test]
+    fn test_every_count() {
+        check_every_count(Field::new(251u16));
+        check_every_count(BinaryField::gf256());
+    }
+
+    #[test]
+    fn test_field_too_small() {
+        let circuit = generate_circuit(&[1; 7], 3, 1, 1, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(7u16));
+        assert_eq!(circuit.err(), Some(CountingError::FieldTooSmall(7)));
+
+        // every weighted vote is counted in the field
+        let circuit = generate_circuit(&[200, 51], 3, 1, 1, ElectionMode::Tally, InvalidBallots::Exclude, &Field::new(251u16));
+        assert_eq!(circuit.err(), Some(CountingError::FieldTooSmall(251)));
+        let circuit = generate_circuit(&[200, 56], 3, 1, 1, ElectionMode::Tally, InvalidBallots::Exclude, &BinaryField::gf256());
+        assert_eq!(circuit.err(), Some(CountingError::FieldTooSmall(256)));
+    }
+
+    #[test]
+    fn test_tally() {
+        let number_of_voters = 4;
+        let number_of_options = 3;
+        let group_order: u16 = 251;
+        let input = vec![vec![0, 0, 1], vec![1, 0, 1], vec![1, 1, 1], vec![1, 0, 1]];
+
+        let circuit = generate_circuit(&vec![1; number_of_voters], 0, number_of_options, number_of_options, ElectionMode::Tally, InvalidBallots::Exclude, &Field::new(group_order)).unwrap();
+
+        assert_eq!(assert_matches_plaintext(Field::new(group_order), circuit, input, 1).unwrap(), all_valid(vec![3, 1, 4], 1, number_of_voters))
+    }
+
+    #[test]
+    fn test_tally_binary_field() {
+        let number_of_voters = 5;
+        let number_of_options = 2;
+        let field = BinaryField::gf256();
+        let input = vec![vec![0, 1], vec![1, 1], vec![1, 1], vec![1, 0], vec![1, 1]];
+
+        let circuit = generate_circuit(&vec![1; number_of_voters], 0, number_of_options, number_of_options, ElectionMode::Tally, InvalidBallots::Exclude, &field).unwrap();
+
+        assert_eq!(assert_matches_plaintext(field, circuit, input, 2).unwrap(), all_valid(vec![4, 4], 1, number_of_voters))
+    }
+
+    fn check_winner(mode: ElectionMode, ballots: Vec<Vec<u16>>, expected: Vec<u16>) {
+        let field = Field::new(251u16);
+        let number_of_voters = ballots.len();
+        let circuit = generate_circuit(&vec![1; number_of_voters], 0, expected.len(), expected.len(), mode, InvalidBallots::Exclude, &field).unwrap();
+
+        assert_eq!(assert_matches_plaintext(field, circuit, ballots, 1).unwrap(), all_valid(expected, 1, number_of_voters))
+    }
+
+    #[test]
+    fn test_plurality() {
+        check_winner(ElectionMode::Plurality, vec![vec![0, 1, 0], vec![0, 0, 1], vec![0, 1, 0]], vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn test_plurality_tie() {
+        check_winner(ElectionMode::Plurality, vec![vec![0, 0, 1], vec![0, 1, 0], vec![1, 0, 0], vec![0, 1, 0], vec![0, 0, 1]], vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn test_borda() {
+        // scores 3, 5, 4: the second option wins although the first one is ranked first most often
+        check_winner(ElectionMode::Borda, vec![vec![2, 0, 1], vec![1, 2, 0], vec![0, 1, 2], vec![0, 2, 1]], vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn test_every_plurality_outcome() {
+        let field = Field::new(251u16);
+        let number_of_voters = 4;
+        let circuit = generate_circuit(&vec![1; number_of_voters], 0, 3, 3, ElectionMode::Plurality, InvalidBallots::Exclude, &field).unwrap();
+        for choices in 0..81usize {
+            let choices: Vec<usize> = (0..number_of_voters).map(|voter| choices / 3usize.pow(voter as u32) % 3).collect();
+            let input: Vec<Vec<u16>> = choices.iter().map(|&choice| (0..3).map(|option| (option == choice) as u16).collect()).collect();
+            let counts: Vec<usize> = (0..3).map(|option| choices.iter().filter(|&&choice| choice == option).count()).collect();
+            let best = (0..3).rev().max_by_key(|&option| counts[option]).unwrap();
+
+            let expected: Vec<u16> = (0..3).map(|option| (option == best) as u16).collect();
+            assert_eq!(evaluate(&circuit, &field, &input)[..3], expected[..], "choices {:?}", choices);
+        }
+    }
+
+    #[test]
+    fn test_plurality_too_heavy() {
+        let circuit = generate_circuit(&[16384; 2], 0, 2, 2, ElectionMode::Plurality, InvalidBallots::Exclude, &Field::new(mpc::field::primes::MERSENNE_61));
+        assert_eq!(circuit.err(), Some(CountingError::FieldTooSmall(65536)));
+
+        // scores up to 3 * 42 are compared through differences up to twice that
+        let circuit = generate_circuit(&[1; 42], 0, 4, 4, ElectionMode::Borda, InvalidBallots::Exclude, &Field::new(251u16));
+        assert_eq!(circuit.err(), Some(CountingError::FieldTooSmall(252)));
+    }
+
+    #[test]
+    fn test_borda_binary_field() {
+        let circuit = generate_circuit(&[1; 3], 0, 3, 3, ElectionMode::Borda, InvalidBallots::Exclude, &BinaryField::gf256());
+        assert_eq!(circuit.err(), Some(CountingError::NotPrimeField));
+    }
+
+    #[test]
+    fn test_zero_threshold() {
+        let circuit = generate_circuit(&[1; 3], 0, 2, 2, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(251u16));
+        assert_eq!(circuit.err(), Some(CountingError::ZeroThreshold));
+    }
+
+    #[test]
+    fn test_invalid_approval_excluded() {
+        let number_of_voters = 4;
+        let number_of_options = 2;
+        let field = Field::new(251u16);
+        // the second voter tries to count seven times, the last one approves too many options
+        let input = vec![vec![1, 0], vec![7, 0], vec![0, 1], vec![1, 1]];
+
+        let circuit = generate_circuit(&vec![1; number_of_voters], 0, number_of_options, 1, ElectionMode::Tally, InvalidBallots::Exclude, &field).unwrap();
+
+        assert_eq!(assert_matches_plaintext(field, circuit, input, 1).unwrap(), vec![1, 1, 1, 0, 1, 0])
+    }
+
+    #[test]
+    fn test_invalid_approval_aborts() {
+        let number_of_voters = 3;
+        let number_of_options = 2;
+        let field = BinaryField::gf256();
+        let input = vec![vec![1, 0], vec![1, 3], vec![1, 1]];
+
+        let circuit = generate_circuit(&vec![1; number_of_voters], 2, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Abort, &field).unwrap();
+
+        assert_eq!(assert_matches_plaintext(field, circuit, input, 1).unwrap(), vec![0, 0, 1, 0, 1])
     }
 
-    fn process_mul_by_const(first: &Gate<u16>, second: u16, field: &Field<u16>) -> u16 {
-        field.mul(first.get_output(), second)
+    #[test]
+    fn test_invalid_plurality() {
+        let number_of_voters = 4;
+        let number_of_options = 2;
+        let field = Field::new(251u16);
+        // a blank ballot, a ballot for both options and a ballot worth -1 to the first option
+        let input = vec![vec![0, 0], vec![1, 1], vec![250, 1], vec![1, 0]];
+
+        let circuit = generate_circuit(&vec![1; number_of_voters], 0, number_of_options, number_of_options, ElectionMode::Plurality, InvalidBallots::Exclude, &field).unwrap();
+
+        assert_eq!(assert_matches_plaintext(field, circuit, input, 1).unwrap(), vec![1, 0, 0, 0, 0, 1])
     }
 
-    fn process_mul(first: &Gate<u16>, second: &Gate<u16>, field: &Field<u16>) -> u16 {
-        field.mul(first.get_output(), second.get_output())
+    #[test]
+    fn test_invalid_ranking() {
+        let number_of_voters = 3;
+        let number_of_options = 3;
+        let field = Field::new(251u16);
+        // the second ballot has the right sum but ranks two options equally
+        let input = vec![vec![0, 1, 2], vec![0, 0, 3], vec![2, 1, 0]];
+
+        let circuit = generate_circuit(&vec![1; number_of_voters], 0, number_of_options, number_of_options, ElectionMode::Borda, InvalidBallots::Abort, &field).unwrap();
+
+        assert_eq!(assert_matches_plaintext(field, circuit, input, 1).unwrap(), vec![0, 0, 0, 1, 0, 1])
+    }
+
+    #[test]
+    fn test_weighted_tally() {
+        let weights = [3, 1, 2];
+        let number_of_options = 2;
+        let input = vec![vec![1, 0], vec![1, 1], vec![0, 1]];
+
+        let circuit = generate_circuit(&weights, 0, number_of_options, number_of_options, ElectionMode::Tally, InvalidBallots::Exclude, &Field::new(251u16)).unwrap();
+        assert_eq!(assert_matches_plaintext(Field::new(251u16), circuit, input.clone(), 1).unwrap(), vec![4, 3, 1, 1, 1]);
+
+        let circuit = generate_circuit(&weights, 0, number_of_options, number_of_options, ElectionMode::Tally, InvalidBallots::Exclude, &BinaryField::gf256()).unwrap();
+        assert_eq!(assert_matches_plaintext(BinaryField::gf256(), circuit, input.into_iter().map(|ballot| ballot.into_iter().map(u64::from).collect()).collect(), 1).unwrap(), vec![4, 3, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_weighted_threshold() {
+        // one share-holder outweighs the two others
+        let weights = [5, 2, 2];
+        let number_of_options = 2;
+        let input = vec![vec![1, 0], vec![0, 1], vec![0, 1]];
+
+        let circuit = generate_circuit(&weights, 5, number_of_options, number_of_options, ElectionMode::Threshold, InvalidBallots::Exclude, &Field::new(251u16)).unwrap();
+
+        assert_eq!(assert_matches_plaintext(Field::new(251u16), circuit, input, 1).unwrap(), vec![1, 0, 1, 1, 1])
+    }
+
+    #[test]
+    fn test_weighted_plurality() {
+        let weights = [4, 1, 1, 1];
+        let number_of_options = 2;
+        let input = vec![vec![0, 1], vec![1, 0], vec![1, 0], vec![1, 0]];
+
+        let circuit = generate_circuit(&weights, 0, number_of_options, number_of_options, ElectionMode::Plurality, InvalidBallots::Exclude, &Field::new(251u16)).unwrap();
+
+        assert_eq!(assert_matches_plaintext(Field::new(251u16), circuit, input, 1).unwrap(), vec![0, 1, 1, 1, 1, 1])
+    }
+
+    #[test]
+    fn test_every_weighted_count() {
+        let weights = [1, 6, 0, 3, 2];
+        for field in [BinaryField::gf256(), BinaryField::gf65536()] {
+            let circuit = generate_circuit(&weights, 0, 1, 1, ElectionMode::Tally, InvalidBallots::Exclude, &field).unwrap();
+            for votes in 0..1u64 << weights.len() {
+                let input: Vec<Vec<u64>> = (0..weights.len()).map(|i| vec![(votes >> i) & 1]).collect();
+                let expected: usize = (0..weights.len()).filter(|&i| (votes >> i) & 1 == 1).map(|i| weights[i]).sum();
+                assert_eq!(evaluate(&circuit, &field, &input)[0], expected as u64, "votes {:b}", votes);
+            }
+        }
     }
 }
@@ -1,74 +1,161 @@
 use std::net::TcpStream;
 use std::io::{Read, Write};
+use std::fmt;
+use std::time::Duration;
+
+use num::ToPrimitive;
 
 use mpc::{
     party::Party,
-    field::Field,
-    message::Message,
+    field::{Field, FiniteField, BinaryField, primes},
+    message::{Message, WireShare},
+    multiplication::Beaver,
     share_receiver::ShareReceiver,
     share_sender::ShareSender,
+    Error,
 };
 
 use crate::{
-    vote_options,
-    util::generate_circuit,
+    vote_options::{self, ElectionField, InvalidBallots},
+    util::{generate_circuit, CountingError},
 };
 
-pub(crate) static GROUP_ORDER: u16 = 251;
+/// Sent by the server once it proxies shares between the voters.
+const PROXY_OPENED: &[u8] = b"Proxy Opened!";
+
+/// How long a voter waits for a single share before giving up on the election.
+const SHARE_TIMEOUT: Duration = Duration::from_secs(60);
 
-pub(crate) fn vote(input: Vec<bool>, vote_options: vote_options::VoteOptions, mut stream: TcpStream) -> Vec<u16> {
-    stream.write(b"VOTED").unwrap();
+/// Why an election produced no results.
+#[derive(Debug)]
+pub(crate) enum VoteError {
+    Protocol(Error),
+    /// The election cannot be counted in its field.
+    Counting(CountingError),
+    /// The ballots of these voters failed the validity checks.
+    InvalidBallots(Vec<usize>),
+}
 
-    let mut data = [0 as u8; 500];
+impl fmt::Display for VoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VoteError::Protocol(e) => write!(f, "{}", e),
+            VoteError::Counting(e) => write!(f, "{}", e),
+            VoteError::InvalidBallots(voters) => write!(
+                f, "invalid ballots from voters {}",
+                voters.iter().map(|voter| voter.to_string()).collect::<Vec<String>>().join(", ")
+            ),
+        }
+    }
+}
 
-    match stream.read(&mut data) {
-        Ok(_) => println!("Protocol started!"),
-        Err(_) => println!("Error when starting protocol"),
-    };
+impl From<Error> for VoteError {
+    fn from(e: Error) -> Self {
+        VoteError::Protocol(e)
+    }
+}
 
+impl From<CountingError> for VoteError {
+    fn from(e: CountingError) -> Self {
+        VoteError::Counting(e)
+    }
+}
+
+impl From<std::io::Error> for VoteError {
+    fn from(e: std::io::Error) -> Self {
+        VoteError::Protocol(e.into())
+    }
+}
+
+pub(crate) fn vote(input: Vec<u16>, vote_options: vote_options::VoteOptions, mut stream: TcpStream) -> Result<Vec<u64>, VoteError> {
+    stream.write_all(b"VOTED")?;
+
+    // NOTE: read exactly the notice, the shares of faster peers may already follow it
+    let mut data = [0u8; PROXY_OPENED.len()];
+    stream.read_exact(&mut data)?;
+    if data != PROXY_OPENED {
+        return Err(Error::Disconnected.into());
+    }
+    println!("Protocol started!");
+
+    // every multiplication is a round trip, so shares must not wait to be coalesced
+    stream.set_nodelay(true)?;
+    // a round waiting for a voter who left fails instead of hanging the election
+    stream.set_read_timeout(Some(SHARE_TIMEOUT))?;
+
+    let mut results = match vote_options.get_field() {
+        ElectionField::P251 => run_party(Field::new(primes::P251), input, &vote_options, &stream),
+        ElectionField::Mersenne61 => run_party(Field::new(primes::MERSENNE_61), input, &vote_options, &stream),
+        ElectionField::Mersenne127 => run_party(Field::new(primes::mersenne_127()), input, &vote_options, &stream),
+        ElectionField::Curve25519 => run_party(Field::new(primes::curve25519_scalar()), input, &vote_options, &stream),
+        ElectionField::Gf256 => run_party(BinaryField::gf256(), input, &vote_options, &stream),
+    }?;
+
+    // the circuit opens a validity bit per voter after the results
+    let validity = results.split_off(vote_options.get_number_of_options());
+    let invalid: Vec<usize> = (0..validity.len()).filter(|&voter| validity[voter] == 0).collect();
+    if !invalid.is_empty() {
+        match vote_options.get_on_invalid() {
+            InvalidBallots::Exclude => println!("Excluded invalid ballots from voters {:?}", invalid),
+            InvalidBallots::Abort => return Err(VoteError::InvalidBallots(invalid)),
+        }
+    }
+
+    Ok(results)
+}
+
+fn run_party<F>(field: F, input: Vec<u16>, vote_options: &vote_options::VoteOptions, stream: &TcpStream) -> Result<Vec<u64>, VoteError>
+where F: FiniteField,
+      F::Element: WireShare + ToPrimitive {
     let rx = Box::new(ShareStream(stream.try_clone().unwrap(), vote_options.get_id()));
     let txs = (0..vote_options.get_number_of_voters()).map(
         |id| Box::new(ShareStream(stream.try_clone().unwrap(), id)) as _
     ).collect();
 
-    Party::new(
+    let (results, misbehaving) = Party::new(
         vote_options.get_id(),
-        input.into_iter().map(u16::from).collect(),
+        input.into_iter().map(|points| field.element(points)).collect(),
         rx,
         txs,
-        Field::new(GROUP_ORDER),
-        generate_circuit(vote_options.get_number_of_voters(), vote_options.get_vote_threshold(), vote_options.get_number_of_options(), GROUP_ORDER),
+        field.clone(),
+        generate_circuit(
+            &vote_options.get_weights(),
+            vote_options.get_vote_threshold(),
+            vote_options.get_number_of_options(),
+            vote_options.get_max_choices(),
+            vote_options.get_mode(),
+            vote_options.get_on_invalid(),
+            &field
+        )?,
         (vote_options.get_number_of_voters() - 1) / 2
-    ).setup().run()
+    )?
+        // Beaver multiplications open values of degree t, so once the triples are dealt every
+        // round goes on with 2t + 1 agreeing shares, which leaves out a voter if n is even
+        .with_multiplication(Beaver)
+        .setup()?.run_with_report()?;
+    if !misbehaving.is_empty() {
+        println!("Corrected wrong shares from voters {:?}", misbehaving);
+    }
+
+    Ok(results.into_iter().map(|result| result.to_u64().unwrap_or(u64::MAX)).collect())
 }
 
 struct ShareStream(TcpStream, usize);
 
-type Msg = Message<u16>;
-
-impl ShareReceiver<Msg> for ShareStream {
-    fn recv(&mut self) -> Msg {
-        let mut data = [0u8; std::mem::size_of::<Msg>()];
-
-        self.0.read_exact(&mut data).unwrap_or_else(|e| println!("Error recv: {}", e));
-        unsafe { std::mem::transmute(data) }
+impl<DataType: WireShare + Clone> ShareReceiver<Message<DataType>> for ShareStream {
+    fn recv(&mut self) -> Result<Message<DataType>, Error> {
+        Ok(Message::read_from(&mut self.0)?)
     }
 }
 
-unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    ::std::slice::from_raw_parts(
-        (p as *const T) as *const u8,
-        ::std::mem::size_of::<T>(),
-    )
-}
-
-impl ShareSender<Msg> for ShareStream {
-    fn send(&mut self, msg: Msg) {
+impl<DataType: WireShare + Clone> ShareSender<Message<DataType>> for ShareStream {
+    fn send(&mut self, msg: Message<DataType>) -> Result<(), Error> {
+        let frame = msg.encode();
         let data = [
             &(self.1 as u64).to_be_bytes()[..],
-            &(std::mem::size_of::<Msg>() as u64).to_be_bytes(),
-            unsafe { any_as_u8_slice(&msg) }
+            &(frame.len() as u64).to_be_bytes(),
+            &frame
         ].concat();
-        self.0.write(&data).unwrap_or_else(|e| { println!("Error send: {}", e); 0 });
+        Ok(self.0.write_all(&data)?)
     }
-}
\ No newline at end of file
+}
@@ -1,18 +1,107 @@
+/// Field the election's shares live in, as announced by the server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ElectionField {
+    P251,
+    Mersenne61,
+    Mersenne127,
+    Curve25519,
+    Gf256,
+}
+
+impl ElectionField {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "p251" => Some(ElectionField::P251),
+            "mersenne61" => Some(ElectionField::Mersenne61),
+            "mersenne127" => Some(ElectionField::Mersenne127),
+            "curve25519" => Some(ElectionField::Curve25519),
+            "gf256" => Some(ElectionField::Gf256),
+            _ => None,
+        }
+    }
+}
+
+/// What an election reveals, as announced by the server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ElectionMode {
+    /// Whether each option got at least `vote_threshold` votes.
+    Threshold,
+    /// The number of votes of each option.
+    Tally,
+    /// Each voter picks one option; only the option with the most votes is revealed.
+    Plurality,
+    /// Each voter ranks all options; only the option with the highest Borda count is revealed.
+    Borda,
+}
+
+impl ElectionMode {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "threshold" => Some(ElectionMode::Threshold),
+            "tally" => Some(ElectionMode::Tally),
+            "plurality" => Some(ElectionMode::Plurality),
+            "borda" => Some(ElectionMode::Borda),
+            _ => None,
+        }
+    }
+}
+
+/// What happens when a voter's ballot fails the validity checks, as announced by the server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum InvalidBallots {
+    /// The ballot is left out of the results.
+    Exclude,
+    /// The results are withheld and the offending voters are named.
+    Abort,
+}
+
+impl InvalidBallots {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "exclude" => Some(InvalidBallots::Exclude),
+            "abort" => Some(InvalidBallots::Abort),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct VoteOptions {
     id: usize,
     number_of_voters: usize,
     vote_threshold: usize,
     number_of_options: usize,
+    field: ElectionField,
+    mode: ElectionMode,
+    on_invalid: InvalidBallots,
+    max_choices: usize,
+    weights: Vec<usize>,
 }
 
 impl VoteOptions {
-    pub(crate) fn new(id: usize, number_of_voters: usize, vote_threshold: usize, number_of_options: usize) -> Self {
+    pub(crate) fn new(id: usize, number_of_voters: usize, vote_threshold: usize, number_of_options: usize, field: ElectionField, mode: ElectionMode) -> Self {
         VoteOptions {
-            id, number_of_voters, vote_threshold, number_of_options
+            id, number_of_voters, vote_threshold, number_of_options, field, mode,
+            on_invalid: InvalidBallots::Exclude,
+            max_choices: number_of_options,
+            weights: vec![1; number_of_voters],
         }
     }
 
+    /// Sets how many times each voter's ballot counts, by voter id.
+    pub(crate) fn with_weights(mut self, weights: Vec<usize>) -> Self {
+        assert_eq!(weights.len(), self.number_of_voters, "expected one weight per voter");
+        self.weights = weights;
+        self
+    }
+
+    /// Sets how invalid ballots are handled and how many options an approval ballot may pick.
+    pub(crate) fn with_ballot_checks(mut self, on_invalid: InvalidBallots, max_choices: usize) -> Self {
+        self.on_invalid = on_invalid;
+        self.max_choices = max_choices;
+        self
+    }
+
     pub(crate) fn get_id(&self) -> usize {
         self.id
     }
@@ -28,4 +117,24 @@ impl VoteOptions {
     pub(crate) fn get_number_of_options(&self) -> usize {
         self.number_of_options
     }
-}
\ No newline at end of file
+
+    pub(crate) fn get_field(&self) -> ElectionField {
+        self.field
+    }
+
+    pub(crate) fn get_mode(&self) -> ElectionMode {
+        self.mode
+    }
+
+    pub(crate) fn get_on_invalid(&self) -> InvalidBallots {
+        self.on_invalid
+    }
+
+    pub(crate) fn get_max_choices(&self) -> usize {
+        self.max_choices
+    }
+
+    pub(crate) fn get_weights(&self) -> Vec<usize> {
+        self.weights.clone()
+    }
+}